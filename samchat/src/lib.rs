@@ -1,10 +1,13 @@
 use hyperprocess_macro::hyperprocess;
-use hyperware_process_lib::{our, Address, Request, ProcessId, homepage::add_to_homepage, LazyLoadBlob};
+use hyperware_app_common::{send, source, SendResult};
+use hyperware_process_lib::{our, net, get_blob, Address, Request, ProcessId, homepage::add_to_homepage, LazyLoadBlob, timer::TimerAction};
+use hyperware_process_lib::http::server::{send_ws_push, WsMessageType};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::Utc;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use serde_json::json;
+use sha2::{Digest, Sha256};
 
 
 // --- Chat Message ---
@@ -18,8 +21,21 @@ pub struct ChatMessage {
     content: String, // The message text
     timestamp: String, // Time the message was sent/received as RFC3339 string for WIT compatibility
     delivered: bool, // Track delivery status
+    #[serde(default)]
+    delivered_to: Vec<String>, // Recipients that acknowledged delivery
+    #[serde(default)]
+    read_by: Vec<String>, // Participants that have read this message
     file_info: Option<FileInfo>, // Optional file attachment info
     reply_to: Option<MessageReplyInfo>, // Optional reply reference
+    #[serde(default)]
+    kind: MessageKind, // User message or system timeline event
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub enum MessageKind {
+    #[default]
+    User,
+    System, // Generated locally to record group changes; never sent to other nodes
 }
 
 // --- Reply Info ---
@@ -38,10 +54,61 @@ pub struct FileInfo {
     mime_type: String,
     file_id: String, // Unique ID for the file in VFS
     sender_node: String, // Node that has the file
+    #[serde(default)]
+    content_hash: Option<String>, // Hex SHA-256 of the content (None for files shared before hashing)
 }
 
-// --- Conversation ---
+// --- Group Roles ---
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum GroupRole {
+    Owner, // Creator of the group; manages admins
+    Admin, // Can add and remove members and change group settings
+    Member,
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct GroupMember {
+    node: String,
+    role: GroupRole,
+}
+
+// --- Group Membership Log ---
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum MembershipAction {
+    Create, // Initial members and roles (also snapshots groups created before the log existed)
+    Add,
+    Remove,
+    Leave,
+    SetRole,
+    TransferOwnership, // Makes the listed member the owner; the previous owner becomes an admin
+    Disband, // Ends the group; later ops have no effect
+}
+
+// One signed change to a group's membership. Every member replays the log in
+// (epoch, op_id) order, so nodes that saw the same ops agree on the result.
 #[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct MembershipOp {
+    group_id: String,
+    epoch: u64, // One past the highest epoch the author had seen
+    op_id: String, // Unique ID; breaks ties between ops with the same epoch
+    author: String, // Node that made the change
+    action: MembershipAction,
+    members: Vec<GroupMember>, // Affected nodes, with their role for Create and SetRole
+    prev: Vec<String>, // Hashes of the latest ops the author had seen; empty for the genesis op
+    timestamp: String, // RFC3339
+    signature: Vec<u8>, // Author's networking key signature over the rest of the op
+}
+
+// Membership derived by replaying a group's log
+struct MembershipReplay {
+    participants: Vec<String>,
+    roles: HashMap<String, GroupRole>,
+    disbanded: bool,
+    revocations: Vec<(usize, String)>, // (index of the op, node it removed or demoted)
+}
+
+// --- Conversation ---
+#[derive(PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Conversation {
     id: String, // Unique ID for the conversation
     participants: Vec<String>, // List of node addresses involved
@@ -50,6 +117,34 @@ pub struct Conversation {
     is_group: bool, // Whether this is a group conversation
     group_name: Option<String>, // Name of the group (if it's a group)
     created_by: Option<String>, // Creator of the group (if it's a group)
+    #[serde(default)]
+    last_read: HashMap<String, String>, // Participant -> ID of the last message they have read
+    #[serde(default)]
+    left: bool, // We left this group; history is kept read-only
+    #[serde(default)]
+    roles: HashMap<String, GroupRole>, // Explicit group roles; everyone else is a member
+    #[serde(default)]
+    membership_log: Vec<MembershipOp>, // Signed membership changes; participants and roles are derived from it
+    #[serde(default)]
+    group_description: Option<String>,
+    #[serde(default)]
+    group_avatar: Option<FileInfo>, // Image stored in VFS like any shared file
+    #[serde(default)]
+    announcement_only: bool, // Only admins may post
+    #[serde(default)]
+    group_info_updated_at: Option<String>, // When name, description, avatar or posting mode last changed
+    #[serde(default)]
+    pending_requests: Vec<JoinRequest>, // Requests to join sent to us as an admin
+    #[serde(default)]
+    disbanded: bool, // The owner ended the group; history is kept read-only
+}
+
+// A node asking to be added to a group
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct JoinRequest {
+    node: String,
+    message: String, // Optional note for the admin (may be empty)
+    requested_at: String,
 }
 
 // --- Conversation Summary for UI ---
@@ -60,6 +155,32 @@ pub struct ConversationSummary {
     last_updated: String, // Last update time as RFC3339 string for WIT compatibility
     is_group: bool,
     group_name: Option<String>,
+    group_description: Option<String>,
+    group_avatar: Option<FileInfo>,
+    announcement_only: bool, // Only admins may post
+    left: bool, // We left this group; history is read-only
+    disbanded: bool, // The group was disbanded by its owner (also read-only)
+    roles: Vec<GroupMember>, // Role of every group participant (empty for direct messages)
+    pending_requests: Vec<JoinRequest>, // Join requests awaiting our approval
+    unread_count: u64, // Messages from others after our read pointer
+    last_message_sender: Option<String>,
+    last_message_preview: Option<String>, // Truncated content (or file name for bare attachments)
+    last_message_timestamp: Option<String>,
+    last_message_has_attachment: bool,
+}
+
+// --- Message Pagination ---
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum PageDirection {
+    Older, // Messages before the cursor
+    Newer, // Messages after the cursor
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MessagePage {
+    messages: Vec<ChatMessage>, // Chronological order, regardless of direction
+    next_cursor: Option<String>, // Continue in the requested direction (None when exhausted)
+    prev_cursor: Option<String>, // Go back the opposite way (None when at that end)
 }
 
 // --- Request/Response Types ---
@@ -69,6 +190,31 @@ pub struct GroupJoinNotification {
     group_name: String,
     participants: Vec<String>,
     created_by: String,
+    #[serde(default)]
+    roles: Vec<GroupMember>,
+    #[serde(default)]
+    membership_log: Vec<MembershipOp>, // Full log, so the new member can verify and replay it
+    #[serde(default)]
+    info: Option<GroupInfoUpdate>, // Current description and avatar
+}
+
+// Sent to a group admin by a node that wants to join
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GroupJoinRequest {
+    group_id: String,
+    message: String,
+}
+
+// Sent to every member when an admin changes the group's name, description or avatar
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GroupInfoUpdate {
+    group_id: String,
+    group_name: String,
+    description: String, // Empty for no description
+    avatar: Option<FileInfo>, // Fetched from the node hosting it like any shared file
+    #[serde(default)]
+    announcement_only: bool,
+    updated_at: String, // RFC3339; updates older than the last applied one are ignored
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -77,6 +223,149 @@ pub struct GroupLeaveNotification {
     member: String,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeliveryAck {
+    conversation_id: String,
+    message_id: String,
+    recipient: String, // Node that received the message
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReadReceipt {
+    conversation_id: String,
+    reader: String, // Node that read the messages
+    up_to_message_id: String, // Last message read; everything before it counts as read too
+}
+
+// --- WebSocket Protocol ---
+// Sent by UI clients on /ws to choose which conversations they get events for
+#[derive(Serialize, Deserialize, Debug)]
+pub enum WsClientMessage {
+    Subscribe { conversation_ids: Vec<String> },
+    Unsubscribe { conversation_ids: Vec<String> },
+    SubscribeAll,
+    UnsubscribeAll,
+}
+
+// Pushed to subscribed UI clients on /ws
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum WsEvent {
+    NewMessage { message: ChatMessage },
+    DeliveryUpdate { conversation_id: String, message_id: String, delivered: bool, delivered_to: Vec<String> },
+    ReadUpdate { conversation_id: String, reader: String, up_to_message_id: String },
+    GroupJoined { conversation_id: String, group_name: String, participants: Vec<String> },
+    GroupInfoUpdated { conversation_id: String, group_name: String, description: Option<String>, avatar: Option<FileInfo>, announcement_only: bool },
+    JoinRequested { conversation_id: String, request: JoinRequest },
+    JoinRequestResolved { conversation_id: String, node: String, approved: bool },
+    GroupMemberAdded { conversation_id: String, member: String },
+    GroupMemberRemoved { conversation_id: String, member: String },
+    GroupMemberLeft { conversation_id: String, member: String },
+    RoleChanged { conversation_id: String, member: String, role: GroupRole },
+    FileTransferProgress { file_id: String, received_bytes: u64, total_size: u64 },
+    GroupDisbanded { conversation_id: String, disbanded_by: String },
+    OutboxEntryFailed { entry_id: String, target_node: String, request_kind: String, last_error: Option<String> },
+}
+
+// Conversations a WebSocket channel is watching
+#[derive(PartialEq, Clone, Default, Debug)]
+pub struct WsSubscription {
+    all: bool,
+    conversation_ids: HashSet<String>,
+}
+
+// --- Chunked File Transfer ---
+// Sent by the downloading node to start a transfer, then once per chunk it pulls.
+// The holder sends each chunk back to receive_file_chunk.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileTransferRequest {
+    transfer_id: String, // Chosen by the downloader to match chunks to the download
+    file_id: String,
+    #[serde(default)]
+    offset: u64, // Where the requested chunk starts
+}
+
+// Header of one chunk; the bytes travel as the request blob
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileChunk {
+    transfer_id: String,
+    file_id: String,
+    offset: u64, // Position of the chunk's first byte in the file
+    total_size: u64,
+}
+
+// Returned by the holder when a transfer starts
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileTransferSummary {
+    total_size: u64,
+    checksum: String, // Hex SHA-256 of the whole file
+}
+
+// A download in progress on this node
+#[derive(Clone, Debug)]
+pub struct IncomingTransfer {
+    file_id: String,
+    source_node: String,
+    next_offset: u64, // Where the next chunk must start
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ByteRange {
+    start: u64,
+    end: u64, // Exclusive
+}
+
+// A download that has not completed yet; its bytes are kept in VFS under files/partial
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct PartialDownload {
+    file_id: String,
+    source_node: String,
+    total_size: Option<u64>, // Known once the first chunk arrives
+    received_ranges: Vec<ByteRange>, // Sorted, non-overlapping
+    updated_at: String,
+}
+
+// --- Group Invites ---
+// Issued by a group admin; anyone holding the code can ask that admin's node to admit them
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct GroupInvite {
+    token: String,
+    group_id: String,
+    code: String, // What the invitee enters: "<issuing node>/<token>"
+    created_at: String,
+    expires_at: Option<String>, // RFC3339; None never expires
+    max_uses: Option<u32>, // None allows unlimited joins
+    uses: u32,
+}
+
+// --- Outbox ---
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    id: String,
+    target_node: String, // Node whose samchat process should receive the request
+    request: serde_json::Value, // Wrapped remote request, e.g. {"ReceiveMessage": ...}
+    attempts: u32,
+    created_at: String,
+    next_attempt_at: String, // RFC3339 time after which the entry may be retried
+    last_error: Option<String>,
+    #[serde(default)]
+    failed: bool, // Gave up after OUTBOX_MAX_AGE_SECS; kept until cancelled
+    #[serde(skip)]
+    in_flight: bool, // An attempt is currently awaiting a response
+}
+
+// --- Outbox Status for UI ---
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OutboxStatus {
+    id: String,
+    target_node: String,
+    request_kind: String, // Remote handler being called, e.g. "ReceiveMessage"
+    attempts: u32,
+    created_at: String,
+    next_attempt_at: String,
+    last_error: Option<String>,
+    failed: bool, // No further attempts will be made
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 enum ResponseType {
     Ack,
@@ -89,10 +378,316 @@ pub struct SamchatState {
     conversations: HashMap<String, Conversation>,
     // Store own node ID after initialization
     my_node_id: Option<String>,
+    // Remote requests waiting to be delivered
+    #[serde(default)]
+    outbox: Vec<OutboxEntry>,
+    // Whether a retry_outbox handler is already waiting on the timer
+    #[serde(skip)]
+    outbox_retry_pending: bool,
+    // File ID -> conversations the file was shared in (controls who may fetch it)
+    #[serde(default)]
+    file_conversations: HashMap<String, Vec<String>>,
+    // Open WebSocket channels and what they subscribed to
+    #[serde(skip)]
+    ws_subscriptions: HashMap<u32, WsSubscription>,
+    // Invites we issued, keyed by token
+    #[serde(default)]
+    group_invites: HashMap<String, GroupInvite>,
+    // Chunked downloads in progress, keyed by transfer ID
+    #[serde(skip)]
+    incoming_transfers: HashMap<String, IncomingTransfer>,
+    // Interrupted or running downloads, keyed by file ID
+    #[serde(default)]
+    partial_downloads: HashMap<String, PartialDownload>,
+    // File ID -> SHA-256 of content we hold, verified locally; content lives at files/<hash>
+    #[serde(default)]
+    file_hashes: HashMap<String, String>,
 }
 
 const ICON: &str = include_str!("icon");
 
+// Timeout for requests to other samchat nodes
+const REMOTE_TIMEOUT_SECS: u64 = 30;
+// Outbox retry backoff: doubles from the base delay up to the cap
+const OUTBOX_BASE_DELAY_SECS: i64 = 5;
+const OUTBOX_MAX_DELAY_SECS: i64 = 600;
+// Page size limits for get_messages_page
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 200;
+// Maximum length of the last-message preview in conversation summaries
+const PREVIEW_MAX_CHARS: usize = 100;
+// Longest the outbox sleeps between retry rounds while entries are pending
+const OUTBOX_MAX_SLEEP_MS: u64 = 30_000;
+// Age after which an undelivered outbox entry is marked failed instead of retried
+const OUTBOX_MAX_AGE_SECS: i64 = 3 * 24 * 60 * 60;
+// Prefix of remote errors that may clear up once earlier requests arrive (e.g. a message
+// for a group whose join notification is still in flight); the outbox keeps retrying them
+const RETRY_LATER_PREFIX: &str = "Retry later: ";
+// Pending join requests kept per group; further requests are refused
+const MAX_PENDING_JOIN_REQUESTS: usize = 100;
+// Where shared files are stored in VFS
+const FILES_DIR: &str = "/samchat:hpn-testing-beta.os/files";
+// Where incomplete downloads are kept until they finish
+const PARTIAL_FILES_DIR: &str = "/samchat:hpn-testing-beta.os/files/partial";
+// Size of each chunk in a peer-to-peer file transfer
+const FILE_CHUNK_SIZE: usize = 256 * 1024;
+// How long a download waits for a chunk that has not arrived with its response, in
+// steps, and how often it asks for the same chunk before giving up
+const CHUNK_WAIT_MS: u64 = 250;
+const CHUNK_WAIT_STEPS: u32 = 20;
+const CHUNK_REQUEST_ATTEMPTS: u32 = 3;
+
+// Address of the samchat process on the given node
+fn samchat_address(node: &str) -> Result<Address, String> {
+    let publisher = "hpn-testing-beta.os";
+    let target_process_id_str = format!("samchat:samchat:{}", publisher);
+    let target_process_id = target_process_id_str.parse::<ProcessId>()
+        .map_err(|e| format!("Failed to parse ProcessId: {}", e))?;
+    Ok(Address::new(node.to_string(), target_process_id))
+}
+
+// Index where a page starts (Newer) or ends, exclusive (Older), for the given cursor.
+// Message IDs are tried first, then RFC3339 timestamps.
+fn resolve_cursor(messages: &[ChatMessage], cursor: &str, direction: &PageDirection) -> Result<usize, String> {
+    if let Some(pos) = messages.iter().position(|m| m.id == cursor) {
+        return Ok(match direction {
+            PageDirection::Older => pos,
+            PageDirection::Newer => pos + 1,
+        });
+    }
+
+    let cursor_time = chrono::DateTime::parse_from_rfc3339(cursor)
+        .map_err(|_| format!("Invalid cursor: {}", cursor))?;
+    let message_time = |m: &ChatMessage| chrono::DateTime::parse_from_rfc3339(&m.timestamp).ok();
+    // Messages are kept in chronological order
+    Ok(match direction {
+        PageDirection::Older => messages.partition_point(|m| message_time(m).is_some_and(|t| t < cursor_time)),
+        PageDirection::Newer => messages.partition_point(|m| message_time(m).is_some_and(|t| t <= cursor_time)),
+    })
+}
+
+// Short preview of a message for conversation lists
+fn message_preview(message: &ChatMessage) -> String {
+    let text = match &message.file_info {
+        Some(file_info) if message.content.trim().is_empty() => file_info.file_name.as_str(),
+        _ => message.content.as_str(),
+    };
+    if text.chars().count() > PREVIEW_MAX_CHARS {
+        let truncated: String = text.chars().take(PREVIEW_MAX_CHARS).collect();
+        format!("{}…", truncated)
+    } else {
+        text.to_string()
+    }
+}
+
+// Node of the samchat process that sent the request being handled.
+// Call before the handler awaits anything, while that request is still the current message.
+fn authenticated_sender() -> Result<String, String> {
+    let source = source();
+    if source.process != our().process {
+        println!("Rejected request from non-samchat process {}", source);
+        return Err("Requests must come from a samchat process".to_string());
+    }
+    Ok(source.node.clone())
+}
+
+// Delay before the next outbox attempt after `attempts` failures
+fn outbox_backoff(attempts: u32) -> chrono::Duration {
+    let secs = OUTBOX_BASE_DELAY_SECS
+        .saturating_mul(1i64 << attempts.saturating_sub(1).min(16))
+        .min(OUTBOX_MAX_DELAY_SECS);
+    chrono::Duration::seconds(secs)
+}
+
+// File IDs are UUIDs generated on upload; anything else could name another path in VFS
+fn validate_file_id(file_id: &str) -> Result<(), String> {
+    Uuid::parse_str(file_id)
+        .map(|_| ())
+        .map_err(|_| format!("Invalid file ID: {}", file_id))
+}
+
+// Whether an RFC3339 time has passed (unparseable times count as due)
+fn is_due(time: &str, now: chrono::DateTime<Utc>) -> bool {
+    chrono::DateTime::parse_from_rfc3339(time)
+        .map(|t| t <= now)
+        .unwrap_or(true)
+}
+
+// Suspend the current handler without blocking the process
+async fn sleep_ms(duration_ms: u64) {
+    let timer_address = Address::new(our().node.clone(), "timer:distro:sys".parse::<ProcessId>().unwrap());
+    // The timer answers with an empty body once the duration has elapsed
+    let _: SendResult<serde_json::Value> = send(TimerAction::SetTimer(duration_ms), &timer_address, duration_ms / 1000 + 1).await;
+}
+
+// Error for a request that depends on state we have not received yet
+fn retry_later(reason: &str) -> String {
+    format!("{}{}", RETRY_LATER_PREFIX, reason)
+}
+
+// Deliver a wrapped request to a remote samchat process; Err means it should be retried
+async fn deliver_remote(target_node: &str, request: &serde_json::Value) -> Result<(), String> {
+    let target_address = samchat_address(target_node)?;
+    match send::<serde_json::Value>(request, &target_address, REMOTE_TIMEOUT_SECS).await {
+        SendResult::Success(response) => {
+            // The remote handler ran; only rejections marked retry-later can change by retrying
+            if let Some(err) = response.get("Err") {
+                if let Some(reason) = err.as_str().filter(|e| e.starts_with(RETRY_LATER_PREFIX)) {
+                    return Err(reason.to_string());
+                }
+                println!("Request to {} was rejected: {}", target_node, err);
+            }
+            Ok(())
+        },
+        SendResult::DeserializationError(e) => {
+            println!("Unexpected response from {}: {}", target_node, e);
+            Ok(())
+        },
+        SendResult::Timeout => Err("Request timed out".to_string()),
+        SendResult::Offline => Err("Node is offline".to_string()),
+    }
+}
+
+// Send an action for `path` to VFS, with `data` as the blob if given, and wait for the response
+fn vfs_request(path: &str, action: serde_json::Value, data: Option<Vec<u8>>) -> Result<hyperware_process_lib::Message, String> {
+    let vfs_address = Address::new(our().node.clone(), "vfs:distro:sys".parse::<ProcessId>().unwrap());
+    let mut request = Request::new()
+        .target(vfs_address)
+        .body(serde_json::to_vec(&json!({ "path": path, "action": action })).unwrap())
+        .expects_response(5);
+    if let Some(data) = data {
+        request = request.blob(LazyLoadBlob::new(Some("file"), data));
+    }
+    request.send_and_await_response(5)
+        .map_err(|e| format!("{:?}", e))?
+        .map_err(|e| format!("{:?}", e))
+}
+
+// Read a whole file from VFS
+fn vfs_read(path: &str) -> Result<Vec<u8>, String> {
+    vfs_request(path, json!("Read"), None)?
+        .blob()
+        .map(|blob| blob.bytes)
+        .ok_or_else(|| "No file data in response".to_string())
+}
+
+// Read `len` bytes of a file in VFS, starting at `offset`
+fn vfs_read_at(path: &str, offset: u64, len: u64) -> Result<Vec<u8>, String> {
+    vfs_request(path, json!({ "Seek": { "Start": offset } }), None)?;
+    vfs_request(path, json!({ "ReadExact": { "length": len } }), None)?
+        .blob()
+        .map(|blob| blob.bytes)
+        .ok_or_else(|| "No file data in response".to_string())
+}
+
+// Size of a file in VFS
+fn vfs_len(path: &str) -> Result<u64, String> {
+    let response = vfs_request(path, json!("Len"), None)?;
+    serde_json::from_slice::<serde_json::Value>(response.body())
+        .ok()
+        .and_then(|body| body.get("Len").and_then(|len| len.as_u64()))
+        .ok_or_else(|| format!("Failed to get the size of {}", path))
+}
+
+// Lowercase hex encoding of a digest
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Lowercase hex SHA-256 digest
+fn sha256_hex(data: &[u8]) -> String {
+    hex_string(&Sha256::digest(data))
+}
+
+// Lowercase hex SHA-256 digest of the first `len` bytes of a VFS file, read a chunk at a time
+fn vfs_sha256_hex(path: &str, len: u64) -> Result<String, String> {
+    let mut hasher = Sha256::new();
+    let mut offset = 0;
+    while offset < len {
+        let chunk_len = (len - offset).min(FILE_CHUNK_SIZE as u64);
+        hasher.update(vfs_read_at(path, offset, chunk_len)?);
+        offset += chunk_len;
+    }
+    Ok(hex_string(&hasher.finalize()))
+}
+
+// Send one chunk of a file to a downloading node without waiting; it checks the chunk
+// against its transfer when it arrives
+fn send_file_chunk(target: &Address, chunk: &FileChunk, bytes: Vec<u8>) -> Result<(), String> {
+    Request::new()
+        .target(target.clone())
+        .body(serde_json::to_vec(&json!({ "ReceiveFileChunk": chunk })).unwrap())
+        .blob(LazyLoadBlob::new(Some("application/octet-stream"), bytes))
+        .send()
+        .map_err(|e| format!("Failed to send chunk at offset {}: {:?}", chunk.offset, e))
+}
+
+// Role phrase for timeline events, e.g. "alice.os made bob.os an admin"
+fn role_description(role: GroupRole) -> &'static str {
+    match role {
+        GroupRole::Owner => "the owner",
+        GroupRole::Admin => "an admin",
+        GroupRole::Member => "a member",
+    }
+}
+
+// Bytes covered by a membership op's signature: the op with the signature left empty
+fn membership_op_payload(op: &MembershipOp) -> Vec<u8> {
+    let mut unsigned = op.clone();
+    unsigned.signature = Vec::new();
+    serde_json::to_vec(&unsigned).unwrap_or_default()
+}
+
+// Identifies a membership op in the `prev` of later ops
+fn membership_op_hash(op: &MembershipOp) -> String {
+    sha256_hex(&membership_op_payload(op))
+}
+
+// Build a membership op authored by us and sign it with our networking key
+fn new_membership_op(group_id: &str, epoch: u64, author: &str, action: MembershipAction, members: Vec<GroupMember>, prev: Vec<String>) -> Result<MembershipOp, String> {
+    let mut op = MembershipOp {
+        group_id: group_id.to_string(),
+        epoch,
+        op_id: Uuid::new_v4().to_string(),
+        author: author.to_string(),
+        action,
+        members,
+        prev,
+        timestamp: Utc::now().to_rfc3339(),
+        signature: Vec::new(),
+    };
+    op.signature = net::sign(membership_op_payload(&op))
+        .map_err(|e| format!("Failed to sign membership change: {:?}", e))?;
+    Ok(op)
+}
+
+// Check that a membership op was signed by the samchat process on its author's node
+fn verify_membership_op(op: &MembershipOp) -> bool {
+    let Ok(author_address) = samchat_address(&op.author) else {
+        return false;
+    };
+    net::verify(author_address, membership_op_payload(op), op.signature.clone()).unwrap_or(false)
+}
+
+// Ask a member for a group's membership ops from `since_epoch` on; ops with bad signatures are dropped
+async fn fetch_membership_log(node: &str, group_id: &str, since_epoch: u64) -> Vec<MembershipOp> {
+    let Ok(address) = samchat_address(node) else {
+        return Vec::new();
+    };
+    let request = json!({ "GetMembershipLog": [group_id, since_epoch] });
+    match send::<Result<Vec<MembershipOp>, String>>(&request, &address, REMOTE_TIMEOUT_SECS).await {
+        SendResult::Success(Ok(ops)) => ops.into_iter().filter(verify_membership_op).collect(),
+        SendResult::Success(Err(e)) => {
+            println!("{} refused membership log for {}: {}", node, group_id, e);
+            Vec::new()
+        },
+        _ => {
+            println!("Failed to fetch membership log for {} from {}", group_id, node);
+            Vec::new()
+        },
+    }
+}
+
 // --- Hyperware Process ---
 #[hyperprocess(
     name = "samchat",
@@ -116,133 +711,73 @@ impl SamchatState {
     #[init]
     async fn initialize(&mut self) {
         println!("Initializing Samchat state...");
-        self.conversations = HashMap::new();
         self.my_node_id = Some(our().node.clone()); // Store own node ID
         println!("Samchat initialized for node: {:?}", self.my_node_id);
 
         add_to_homepage("SamChat", Some(ICON), Some(""), None);
+
+        // Resume delivery of anything left in the outbox before the restart
+        if !self.outbox.is_empty() {
+            println!("Resuming {} pending outbox entries", self.outbox.len());
+            self.kick_outbox();
+        }
     }
 
-    // Upload a file and store it in VFS
+    // Upload a file and store it in VFS. Identical content is stored only once.
     #[http]
     async fn upload_file(&mut self, file_name: String, mime_type: String, file_data: Vec<u8>) -> Result<FileInfo, String> {
         println!("upload_file called: name={}, type={}, size={}", file_name, mime_type, file_data.len());
         
         let file_id = Uuid::new_v4().to_string();
-        let file_path = format!("/samchat:hpn-testing-beta.os/files/{}", file_id);
         let file_size = file_data.len() as u64;
+        let content_hash = sha256_hex(&file_data);
         
-        // Create VFS request to write file
-        let vfs_address = Address::new(our().node.clone(), "vfs:distro:sys".parse::<ProcessId>().unwrap());
-        
-        // First create the directory if it doesn't exist
-        let create_dir_request = json!({
-            "path": "/samchat:hpn-testing-beta.os/files",
-            "action": "CreateDirAll"
-        });
-        
-        let dir_result = Request::new()
-            .target(vfs_address.clone())
-            .body(serde_json::to_vec(&create_dir_request).unwrap())
-            .expects_response(5)
-            .send_and_await_response(5).unwrap();
-            
-        if let Err(e) = dir_result {
-            println!("Failed to create directory: {:?}", e);
-            // Continue anyway, directory might already exist
-        }
-        
-        // Write the file
-        let write_request = json!({
-            "path": file_path,
-            "action": "Write"
-        });
-        
-        let write_result = Request::new()
-            .target(vfs_address)
-            .body(serde_json::to_vec(&write_request).unwrap())
-            .blob(LazyLoadBlob::new(Some("file"), file_data))
-            .expects_response(5)
-            .send_and_await_response(5).unwrap();
-        
-        match write_result {
-            Ok(_) => {
-                println!("File {} uploaded successfully", file_id);
-                Ok(FileInfo {
-                    file_name,
-                    file_size,
-                    mime_type,
-                    file_id,
-                    sender_node: our().node.clone(),
-                })
-            },
-            Err(e) => {
-                println!("Failed to upload file: {:?}", e);
-                Err(format!("Failed to upload file: {:?}", e))
-            }
-        }
+        self.store_file_content(&file_id, &content_hash, file_data)
+            .map_err(|e| {
+                println!("Failed to upload file: {}", e);
+                format!("Failed to upload file: {}", e)
+            })?;
+        println!("File {} uploaded successfully", file_id);
+        Ok(FileInfo {
+            file_name,
+            file_size,
+            mime_type,
+            file_id,
+            sender_node: our().node.clone(),
+            content_hash: Some(content_hash),
+        })
     }
     
     // Get file from remote node (called by other nodes)
     #[remote]
     async fn get_remote_file(&self, file_id: String) -> Result<Vec<u8>, String> {
         println!("get_remote_file called: id={}", file_id);
-        
-        let file_path = format!("/samchat:hpn-testing-beta.os/files/{}", file_id);
-        let vfs_address = Address::new(our().node.clone(), "vfs:distro:sys".parse::<ProcessId>().unwrap());
-        
-        let read_request = json!({
-            "path": file_path,
-            "action": "Read"
-        });
-        
-        let read_result = Request::new()
-            .target(vfs_address)
-            .body(serde_json::to_vec(&read_request).unwrap())
-            .expects_response(5)
-            .send_and_await_response(5).unwrap();
-        
-        match read_result {
-            Ok(response) => {
-                if let Some(blob) = response.blob() {
-                    Ok(blob.bytes)
-                } else {
-                    Err("No file data in response".to_string())
-                }
-            },
-            Err(e) => {
-                println!("Failed to read file locally: {:?}", e);
-                Err(format!("File not found: {:?}", e))
-            }
+
+        // Only participants of a conversation the file was shared in may fetch it
+        let from = authenticated_sender()?;
+        if !self.may_fetch_file(&file_id, &from) {
+            println!("Forbidden: {} requested file {} without access", from, file_id);
+            return Err(format!("Forbidden: file {} was not shared with {}", file_id, from));
         }
+        
+        vfs_read(&self.file_path(&file_id)).map_err(|e| {
+            println!("Failed to read file locally: {}", e);
+            format!("File not found: {}", e)
+        })
     }
 
-    // Download a file (tries local first, then remote)
+    // Download a file (tries local first, then pulls it from the sender one chunk at a time).
+    // Interrupted downloads keep what they received and resume from there next time.
     #[http]
     async fn download_file(&mut self, file_id: String, sender_node: String) -> Result<Vec<u8>, String> {
         println!("download_file called: id={}, sender={}", file_id, sender_node);
-        
-        let file_path = format!("/samchat:hpn-testing-beta.os/files/{}", file_id);
-        let vfs_address = Address::new(our().node.clone(), "vfs:distro:sys".parse::<ProcessId>().unwrap());
-        
+        validate_file_id(&file_id)?;
+
         // First try to read locally
-        let read_request = json!({
-            "path": file_path.clone(),
-            "action": "Read"
-        });
-        
-        let local_result = Request::new()
-            .target(vfs_address.clone())
-            .body(serde_json::to_vec(&read_request).unwrap())
-            .expects_response(5)
-            .send_and_await_response(5).unwrap();
-        
-        match local_result {
-            Ok(response) => {
-                if let Some(blob) = response.blob() {
-                    println!("File found locally");
-                    return Ok(blob.bytes);
-                }
+        match vfs_read(&self.file_path(&file_id)) {
+            Ok(bytes) => {
+                println!("File found locally");
+                return Ok(bytes);
             },
             Err(_) => {
                 println!("File not found locally, fetching from remote node");
@@ -250,73 +785,178 @@ impl SamchatState {
         }
         
         // If not found locally and sender is different node, fetch from remote
-        if sender_node != our().node {
-            let publisher = "hpn-testing-beta.os";
-            let target_process_id_str = format!("samchat:samchat:{}", publisher);
-            let target_process_id = target_process_id_str.parse::<ProcessId>()
-                .map_err(|e| format!("Failed to parse ProcessId: {}", e))?;
-            
-            let target_address = Address::new(sender_node.clone(), target_process_id);
-            let request_wrapper = json!({
-                "GetRemoteFile": file_id.clone()
-            });
-            
-            let remote_result = Request::new()
-                .target(target_address)
-                .body(serde_json::to_vec(&request_wrapper).unwrap())
-                .expects_response(30)
-                .send_and_await_response(30).unwrap();
-            
-            match remote_result {
-                Ok(response) => {
-                    // Parse the response to get the file data
-                    let response_json: serde_json::Value = serde_json::from_slice(&response.body())
-                        .map_err(|e| format!("Failed to parse response: {}", e))?;
-                    
-                    if let Some(file_data_json) = response_json.get("Ok") {
-                        let file_data: Vec<u8> = serde_json::from_value(file_data_json.clone())
-                            .map_err(|e| format!("Failed to parse file data: {}", e))?;
-                        
-                        // Save to local VFS for future use
-                        // First ensure directory exists
-                        let create_dir_request = json!({
-                            "path": "/samchat:hpn-testing-beta.os/files",
-                            "action": "CreateDirAll"
-                        });
-                        
-                        let _ = Request::new()
-                            .target(vfs_address.clone())
-                            .body(serde_json::to_vec(&create_dir_request).unwrap())
-                            .expects_response(5)
-                            .send_and_await_response(5).unwrap();
-                        
-                        // Now write the file
-                        let write_request = json!({
-                            "path": file_path,
-                            "action": "Write"
-                        });
-                        
-                        let _ = Request::new()
-                            .target(vfs_address)
-                            .body(serde_json::to_vec(&write_request).unwrap())
-                            .blob(LazyLoadBlob::new(Some("file"), file_data.clone()))
-                            .expects_response(5)
-                            .send_and_await_response(5).unwrap();
-                        
-                        println!("File fetched from remote and cached locally");
-                        return Ok(file_data);
-                    } else if let Some(err) = response_json.get("Err") {
-                        return Err(format!("Remote error: {}", err));
-                    }
-                },
-                Err(e) => {
-                    println!("Failed to fetch from remote: {:?}", e);
-                    return Err(format!("Failed to fetch file from remote node: {:?}", e));
-                }
+        if sender_node == our().node {
+            return Err("File not found".to_string());
+        }
+        if self.incoming_transfers.values().any(|t| t.file_id == file_id) {
+            return Err("This file is already being downloaded".to_string());
+        }
+
+        let transfer_id = Uuid::new_v4().to_string();
+        let target_address = samchat_address(&sender_node)?;
+        let request = json!({
+            "StartFileTransfer": FileTransferRequest {
+                transfer_id: transfer_id.clone(),
+                file_id: file_id.clone(),
+                offset: 0,
             }
+        });
+        let summary = match send::<Result<FileTransferSummary, String>>(&request, &target_address, REMOTE_TIMEOUT_SECS).await {
+            SendResult::Success(Ok(summary)) => summary,
+            SendResult::Success(Err(e)) => return Err(format!("Remote error: {}", e)),
+            SendResult::Timeout => return Err("Sender did not respond".to_string()),
+            SendResult::Offline => return Err("Sender node is offline".to_string()),
+            SendResult::DeserializationError(e) => return Err(format!("Unexpected response from sender: {}", e)),
+        };
+
+        // Resume a partial download of the same file from the same sender, or start over
+        let partial_path = format!("{}/{}", PARTIAL_FILES_DIR, file_id);
+        let offset = match self.partial_downloads.get(&file_id) {
+            Some(partial) if partial.source_node == sender_node && partial.total_size.unwrap_or(summary.total_size) == summary.total_size => {
+                partial.contiguous_bytes().min(summary.total_size)
+            },
+            _ => 0,
+        };
+        vfs_request(PARTIAL_FILES_DIR, json!("CreateDirAll"), None)?;
+        if offset == 0 {
+            vfs_request(&partial_path, json!("Write"), Some(Vec::new()))?;
+            self.partial_downloads.insert(file_id.clone(), PartialDownload {
+                file_id: file_id.clone(),
+                source_node: sender_node.clone(),
+                total_size: Some(summary.total_size),
+                received_ranges: Vec::new(),
+                updated_at: Utc::now().to_rfc3339(),
+            });
+        } else {
+            // Drop anything written past the last recorded chunk
+            vfs_request(&partial_path, json!({ "SetLen": offset }), None)?;
+            println!("Resuming download of {} from byte {}", file_id, offset);
         }
-        
-        Err("File not found".to_string())
+
+        // Pull the rest one chunk at a time; receive_file_chunk appends each one
+        self.incoming_transfers.insert(transfer_id.clone(), IncomingTransfer {
+            file_id: file_id.clone(),
+            source_node: sender_node.clone(),
+            next_offset: offset,
+        });
+        let result = self.pull_file_chunks(&transfer_id, &file_id, &target_address, summary.total_size).await;
+        let transfer = self.incoming_transfers.remove(&transfer_id)
+            .ok_or_else(|| "Transfer state lost".to_string())?;
+        if let Err(e) = result {
+            return Err(format!("{}; {} bytes kept for resuming", e, transfer.next_offset));
+        }
+
+        let content_hash = vfs_sha256_hex(&partial_path, summary.total_size)?;
+        if vfs_len(&partial_path)? != summary.total_size || content_hash != summary.checksum {
+            // Something in the partial file is wrong; the next attempt starts from scratch
+            self.partial_downloads.remove(&file_id);
+            return Err("Checksum mismatch in transferred file".to_string());
+        }
+        // The sender must deliver the content it announced when sharing the file
+        if self.announced_hash(&file_id).is_some_and(|announced| announced != content_hash) {
+            self.partial_downloads.remove(&file_id);
+            println!("Rejected file {} from {}: content does not match the shared hash", file_id, sender_node);
+            return Err("File content does not match its shared hash".to_string());
+        }
+
+        // Move the completed file into place for future use
+        self.store_file_from_path(&file_id, &content_hash, &partial_path)?;
+        self.partial_downloads.remove(&file_id);
+        println!("File fetched from remote in chunks and cached locally");
+        vfs_read(&self.file_path(&file_id))
+    }
+
+    // Describe a file the requesting node wants to download. It then pulls the file
+    // one chunk at a time with request_file_chunk.
+    #[remote]
+    async fn start_file_transfer(&self, request: FileTransferRequest) -> Result<FileTransferSummary, String> {
+        println!("start_file_transfer called: id={}", request.file_id);
+
+        let from = authenticated_sender()?;
+        if !self.may_fetch_file(&request.file_id, &from) {
+            println!("Forbidden: {} requested file {} without access", from, request.file_id);
+            return Err(format!("Forbidden: file {} was not shared with {}", request.file_id, from));
+        }
+
+        let path = self.file_path(&request.file_id);
+        let total_size = vfs_len(&path).map_err(|e| format!("File not found: {}", e))?;
+        // Files stored before content hashing have no recorded hash
+        let checksum = match self.file_hashes.get(&request.file_id) {
+            Some(hash) => hash.clone(),
+            None => vfs_sha256_hex(&path, total_size)?,
+        };
+        Ok(FileTransferSummary { total_size, checksum })
+    }
+
+    // Send the requesting node the chunk of a file that starts at the requested offset.
+    // The bytes go out as a separate request with a blob, ahead of this response, which
+    // only describes the chunk.
+    #[remote]
+    async fn request_file_chunk(&self, request: FileTransferRequest) -> Result<FileChunk, String> {
+        let from = authenticated_sender()?;
+        if !self.may_fetch_file(&request.file_id, &from) {
+            return Err(format!("Forbidden: file {} was not shared with {}", request.file_id, from));
+        }
+
+        let path = self.file_path(&request.file_id);
+        let total_size = vfs_len(&path).map_err(|e| format!("File not found: {}", e))?;
+        if request.offset >= total_size {
+            return Err(format!("Offset {} is past the end of the file", request.offset));
+        }
+        let len = (total_size - request.offset).min(FILE_CHUNK_SIZE as u64);
+        let bytes = vfs_read_at(&path, request.offset, len)?;
+        let chunk = FileChunk {
+            transfer_id: request.transfer_id,
+            file_id: request.file_id,
+            offset: request.offset,
+            total_size,
+        };
+        send_file_chunk(&samchat_address(&from)?, &chunk, bytes)?;
+        Ok(chunk)
+    }
+
+    // Accept one chunk of a file we are downloading; the bytes are in the request blob
+    #[remote]
+    async fn receive_file_chunk(&mut self, chunk: FileChunk) -> Result<bool, String> {
+        let from = authenticated_sender()?;
+        let bytes = get_blob()
+            .map(|blob| blob.bytes)
+            .ok_or_else(|| "Chunk has no data".to_string())?;
+
+        let transfer = self.incoming_transfers.get_mut(&chunk.transfer_id)
+            .ok_or_else(|| "Unknown transfer".to_string())?;
+        if transfer.source_node != from || transfer.file_id != chunk.file_id {
+            return Err("Chunk does not belong to this transfer".to_string());
+        }
+        // A chunk that was requested again can turn up twice; the late copy is dropped
+        if chunk.offset < transfer.next_offset {
+            return Ok(false);
+        }
+        if chunk.offset != transfer.next_offset {
+            return Err(format!("Expected chunk at offset {}", transfer.next_offset));
+        }
+        let end = chunk.offset + bytes.len() as u64;
+        if bytes.len() > FILE_CHUNK_SIZE || end > chunk.total_size {
+            return Err("Chunk exceeds the file size".to_string());
+        }
+
+        // Persist before recording the range, so a recorded range is always on disk
+        vfs_request(&format!("{}/{}", PARTIAL_FILES_DIR, chunk.file_id), json!("Append"), Some(bytes))?;
+        transfer.next_offset = end;
+        if let Some(partial) = self.partial_downloads.get_mut(&chunk.file_id) {
+            partial.total_size = Some(chunk.total_size);
+            partial.add_range(chunk.offset, end);
+            partial.updated_at = Utc::now().to_rfc3339();
+        }
+
+        let event = WsEvent::FileTransferProgress {
+            file_id: chunk.file_id.clone(),
+            received_bytes: end,
+            total_size: chunk.total_size,
+        };
+        let conversation_ids = self.file_conversations.get(&chunk.file_id).cloned().unwrap_or_default();
+        self.push_where(event, |s| conversation_ids.iter().any(|id| s.watches(id)));
+        Ok(true)
     }
 
     // Send a file message
@@ -335,6 +975,15 @@ impl SamchatState {
             conversation_id = recipient_address.clone();
             let conversation = self.conversations.get(&conversation_id)
                 .ok_or_else(|| "Group conversation not found".to_string())?;
+            if conversation.disbanded {
+                return Err("This group has been disbanded".to_string());
+            }
+            if conversation.left {
+                return Err("You are no longer a member of this group".to_string());
+            }
+            if conversation.announcement_only && !conversation.can_manage(&sender_address) {
+                return Err("Only admins can post in this group".to_string());
+            }
             recipients = conversation.participants.iter()
                 .filter(|p| *p != &sender_address)
                 .cloned()
@@ -358,25 +1007,18 @@ impl SamchatState {
             content: message_content,
             timestamp: current_time_str.clone(),
             delivered: false,
-            file_info: Some(file_info),
+            delivered_to: Vec::new(),
+            read_by: Vec::new(),
+            file_info: Some(file_info.clone()),
             reply_to: None,
+            kind: MessageKind::User,
         };
+        self.record_shared_file(&file_info.file_id, &conversation_id);
 
         // Persist locally
         if !is_group {
-            let conversation = self.conversations.entry(conversation_id.clone()).or_insert_with(|| {
-                let mut participants = vec![sender_address.clone(), recipient_address.clone()];
-                participants.sort();
-                Conversation {
-                    id: conversation_id.clone(),
-                    participants: participants.clone(),
-                    messages: Vec::new(),
-                    last_updated: current_time_str.clone(),
-                    is_group: false,
-                    group_name: None,
-                    created_by: None,
-                }
-            });
+            let conversation = self.conversations.entry(conversation_id.clone())
+                .or_insert_with(|| Conversation::direct(&conversation_id, &sender_address, &recipient_address, &current_time_str));
             conversation.messages.push(message.clone());
             conversation.last_updated = current_time_str.clone();
         } else {
@@ -386,25 +1028,11 @@ impl SamchatState {
             conversation.last_updated = current_time_str.clone();
         }
 
-        // Send to recipients
-        let publisher = "hpn-testing-beta.os";
-        let target_process_id_str = format!("samchat:samchat:{}", publisher);
-        let target_process_id = target_process_id_str.parse::<ProcessId>()
-            .map_err(|e| format!("Failed to parse ProcessId: {}", e))?;
-        
+        // Queue delivery to recipients
         for recipient in recipients {
-            let target_address = Address::new(recipient.clone(), target_process_id.clone());
-            let request_wrapper = serde_json::json!({
+            self.enqueue_remote(&recipient, json!({
                 "ReceiveMessage": message.clone()
-            });
-            let request_body = serde_json::to_vec(&request_wrapper)
-                .map_err(|e| format!("Serialization error: {}", e))?;
-
-            let _ = Request::new()
-                .target(target_address)
-                .body(request_body)
-                .expects_response(30)
-                .send();
+            }));
         }
         
         Ok(true)
@@ -435,6 +1063,15 @@ impl SamchatState {
             conversation_id = recipient_address.clone();
             let conversation = self.conversations.get(&conversation_id)
                 .ok_or_else(|| "Group conversation not found".to_string())?;
+            if conversation.disbanded {
+                return Err("This group has been disbanded".to_string());
+            }
+            if conversation.left {
+                return Err("You are no longer a member of this group".to_string());
+            }
+            if conversation.announcement_only && !conversation.can_manage(&sender_address) {
+                return Err("Only admins can post in this group".to_string());
+            }
             recipients = conversation.participants.iter()
                 .filter(|p| *p != &sender_address)
                 .cloned()
@@ -458,25 +1095,17 @@ impl SamchatState {
             content: message_content,
             timestamp: current_time_str.clone(),
             delivered: false,
+            delivered_to: Vec::new(),
+            read_by: Vec::new(),
             file_info: None,
             reply_to: reply_info,
+            kind: MessageKind::User,
         };
 
         // Persist locally
         if !is_group {
-            let conversation = self.conversations.entry(conversation_id.clone()).or_insert_with(|| {
-                let mut participants = vec![sender_address.clone(), recipient_address.clone()];
-                participants.sort();
-                Conversation {
-                    id: conversation_id.clone(),
-                    participants: participants.clone(),
-                    messages: Vec::new(),
-                    last_updated: current_time_str.clone(),
-                    is_group: false,
-                    group_name: None,
-                    created_by: None,
-                }
-            });
+            let conversation = self.conversations.entry(conversation_id.clone())
+                .or_insert_with(|| Conversation::direct(&conversation_id, &sender_address, &recipient_address, &current_time_str));
             conversation.messages.push(message.clone());
             conversation.last_updated = current_time_str.clone();
         } else {
@@ -487,25 +1116,11 @@ impl SamchatState {
         }
         println!("Message persisted locally: {}", message.id);
 
-        // Send to recipients
-        let publisher = "hpn-testing-beta.os";
-        let target_process_id_str = format!("samchat:samchat:{}", publisher);
-        let target_process_id = target_process_id_str.parse::<ProcessId>()
-            .map_err(|e| format!("Failed to parse ProcessId: {}", e))?;
-        
+        // Queue delivery to recipients
         for recipient in recipients {
-            let target_address = Address::new(recipient.clone(), target_process_id.clone());
-            let request_wrapper = serde_json::json!({
+            self.enqueue_remote(&recipient, json!({
                 "ReceiveMessage": message.clone()
-            });
-            let request_body = serde_json::to_vec(&request_wrapper)
-                .map_err(|e| format!("Serialization error: {}", e))?;
-
-            let _ = Request::new()
-                .target(target_address)
-                .body(request_body)
-                .expects_response(30)
-                .send();
+            }));
         }
         
         Ok(true)
@@ -543,49 +1158,44 @@ impl SamchatState {
         // Generate unique group ID
         let group_id = format!("group_{}", Uuid::new_v4());
         let current_time_str = Utc::now().to_rfc3339();
+
+        // The signed create op is epoch 0 of the group's membership log
+        let initial_roles: Vec<GroupMember> = participants.iter()
+            .map(|p| GroupMember {
+                node: p.clone(),
+                role: if p == &creator { GroupRole::Owner } else { GroupRole::Member },
+            })
+            .collect();
+        let genesis = new_membership_op(&group_id, 0, &creator, MembershipAction::Create, initial_roles, Vec::new())?;
         
         // Create the group conversation locally
-        let conversation = Conversation {
-            id: group_id.clone(),
-            participants: participants.clone(),
-            messages: Vec::new(),
-            last_updated: current_time_str.clone(),
-            is_group: true,
-            group_name: Some(group_name.clone()),
-            created_by: Some(creator.clone()),
-        };
+        let mut conversation = Conversation::group(&group_id, participants.clone(), &group_name, Some(creator.clone()), &current_time_str);
+        conversation.group_info_updated_at = Some(current_time_str.clone());
+        conversation.merge_membership_ops(vec![genesis]);
+        let roles = conversation.member_roles();
+        let membership_log = conversation.membership_log.clone();
+        let info = conversation.group_info();
         
         self.conversations.insert(group_id.clone(), conversation);
+        self.record_system_event(&group_id, &creator, format!("{} created the group \"{}\"", creator, group_name));
         println!("Group created locally: {}", group_id);
         
         // Notify all other members about the new group
-        let publisher = "hpn-testing-beta.os";
-        let target_process_id_str = format!("samchat:samchat:{}", publisher);
-        let target_process_id = target_process_id_str.parse::<ProcessId>()
-            .map_err(|e| format!("Failed to parse ProcessId: {}", e))?;
-        
         for participant in &participants {
             if participant != &creator {
-                let target_address = Address::new(participant.clone(), target_process_id.clone());
                 let notification = GroupJoinNotification {
                     group_id: group_id.clone(),
                     group_name: group_name.clone(),
                     participants: participants.clone(),
                     created_by: creator.clone(),
+                    roles: roles.clone(),
+                    membership_log: membership_log.clone(),
+                    info: Some(info.clone()),
                 };
-                let request_wrapper = serde_json::json!({
-                    "HandleGroupJoin": notification
-                });
-                let request_body = serde_json::to_vec(&request_wrapper)
-                    .map_err(|e| format!("Serialization error: {}", e))?;
-                
                 println!("Notifying {} about new group {}", participant, group_id);
-                // Use expects_response for remote calls
-                let _ = Request::new()
-                    .target(target_address)
-                    .body(request_body)
-                    .expects_response(30)
-                    .send();
+                self.enqueue_remote(participant, json!({
+                    "HandleGroupJoin": notification
+                }));
             }
         }
         
@@ -597,15 +1207,21 @@ impl SamchatState {
     async fn add_group_member(&mut self, group_id: String, new_member: String) -> Result<bool, String> {
         println!("add_group_member called: group={}, member={}", group_id, new_member);
         
-        let _requester = self.my_node_id.clone().ok_or_else(|| "Requester node ID not initialized".to_string())?;
+        let requester = self.my_node_id.clone().ok_or_else(|| "Requester node ID not initialized".to_string())?;
         
         // Get the group conversation
-        let conversation = self.conversations.get_mut(&group_id)
+        let conversation = self.conversations.get(&group_id)
             .ok_or_else(|| "Group not found".to_string())?;
         
         if !conversation.is_group {
             return Err("Not a group conversation".to_string());
         }
+        if conversation.left {
+            return Err("You have left this group".to_string());
+        }
+        if !conversation.can_manage(&requester) {
+            return Err("Only group admins can add members".to_string());
+        }
         
         // Check if member already exists
         if conversation.participants.contains(&new_member) {
@@ -613,34 +1229,478 @@ impl SamchatState {
         }
         
         // Add the new member
-        conversation.participants.push(new_member.clone());
-        conversation.last_updated = Utc::now().to_rfc3339();
-        
-        // Notify the new member about the group
-        let publisher = "hpn-testing-beta.os";
-        let target_process_id_str = format!("samchat:samchat:{}", publisher);
-        let target_process_id = target_process_id_str.parse::<ProcessId>()
-            .map_err(|e| format!("Failed to parse ProcessId: {}", e))?;
+        self.admit_group_member(&group_id, &new_member)?;
         
-        let target_address = Address::new(new_member.clone(), target_process_id);
-        let notification = GroupJoinNotification {
-            group_id: group_id.clone(),
-            group_name: conversation.group_name.clone().unwrap_or_default(),
-            participants: conversation.participants.clone(),
-            created_by: conversation.created_by.clone().unwrap_or_default(),
+        Ok(true)
+    }
+
+    // Remove a member from a group (admins only; only the owner can remove admins)
+    #[http]
+    async fn remove_group_member(&mut self, group_id: String, member: String) -> Result<bool, String> {
+        println!("remove_group_member called: group={}, member={}", group_id, member);
+        let me = self.my_node_id.clone().ok_or_else(|| "Node ID not initialized".to_string())?;
+
+        let conversation = self.conversations.get(&group_id)
+            .ok_or_else(|| "Group not found".to_string())?;
+        if !conversation.is_group || conversation.left {
+            return Err("Not an active group conversation".to_string());
+        }
+        if !conversation.can_manage(&me) {
+            return Err("Only group admins can remove members".to_string());
+        }
+        if member == me {
+            return Err("Use leave_group to leave a group".to_string());
+        }
+        if !conversation.participants.contains(&member) {
+            return Err("Not a member of this group".to_string());
+        }
+        match conversation.role_of(&member) {
+            GroupRole::Owner => return Err("The group owner cannot be removed".to_string()),
+            GroupRole::Admin if conversation.role_of(&me) != GroupRole::Owner => {
+                return Err("Only the group owner can remove admins".to_string());
+            },
+            _ => {},
+        }
+
+        let removed = GroupMember { node: member, role: GroupRole::Member };
+        let (ops, before) = self.commit_membership_op(&group_id, MembershipAction::Remove, vec![removed])?;
+
+        // The removed node gets the op too, so its copy becomes read-only
+        let others: Vec<String> = before.iter()
+            .map(|m| m.node.clone())
+            .filter(|p| p != &me)
+            .collect();
+        self.send_membership_ops(&ops, &others);
+        self.announce_membership_changes(&group_id, &me, &before, &ops);
+        Ok(true)
+    }
+
+    // Promote a member to admin or demote an admin (owner only)
+    #[http]
+    async fn set_member_role(&mut self, group_id: String, member: String, role: GroupRole) -> Result<bool, String> {
+        println!("set_member_role called: group={}, member={}, role={:?}", group_id, member, role);
+        let me = self.my_node_id.clone().ok_or_else(|| "Node ID not initialized".to_string())?;
+
+        let conversation = self.conversations.get(&group_id)
+            .ok_or_else(|| "Group not found".to_string())?;
+        if !conversation.is_group || conversation.left {
+            return Err("Not an active group conversation".to_string());
+        }
+        if conversation.role_of(&me) != GroupRole::Owner {
+            return Err("Only the group owner can change roles".to_string());
+        }
+        if role == GroupRole::Owner || member == me {
+            return Err("Ownership cannot be changed with set_member_role".to_string());
+        }
+        if !conversation.participants.contains(&member) {
+            return Err("Not a member of this group".to_string());
+        }
+
+        let (ops, before) = self.commit_membership_op(&group_id, MembershipAction::SetRole, vec![GroupMember { node: member, role }])?;
+        let others: Vec<String> = before.iter()
+            .map(|m| m.node.clone())
+            .filter(|p| p != &me)
+            .collect();
+        self.send_membership_ops(&ops, &others);
+        self.announce_membership_changes(&group_id, &me, &before, &ops);
+        Ok(true)
+    }
+
+    // Hand ownership of a group to another member (owner only); we stay on as an admin
+    #[http]
+    async fn transfer_group_ownership(&mut self, group_id: String, new_owner: String) -> Result<bool, String> {
+        println!("transfer_group_ownership called: group={}, new_owner={}", group_id, new_owner);
+        let me = self.my_node_id.clone().ok_or_else(|| "Node ID not initialized".to_string())?;
+
+        let conversation = self.conversations.get(&group_id)
+            .ok_or_else(|| "Group not found".to_string())?;
+        if !conversation.is_group || conversation.left {
+            return Err("Not an active group conversation".to_string());
+        }
+        if conversation.role_of(&me) != GroupRole::Owner {
+            return Err("Only the group owner can transfer ownership".to_string());
+        }
+        if new_owner == me || !conversation.participants.contains(&new_owner) {
+            return Err("The new owner must be another member of the group".to_string());
+        }
+
+        let target = GroupMember { node: new_owner, role: GroupRole::Owner };
+        let (ops, before) = self.commit_membership_op(&group_id, MembershipAction::TransferOwnership, vec![target])?;
+        let others: Vec<String> = before.iter()
+            .map(|m| m.node.clone())
+            .filter(|p| p != &me)
+            .collect();
+        self.send_membership_ops(&ops, &others);
+        self.announce_membership_changes(&group_id, &me, &before, &ops);
+        Ok(true)
+    }
+
+    // Disband a group (owner only). Every member keeps a read-only copy of the history
+    // and no further messages are accepted for it.
+    #[http]
+    async fn disband_group(&mut self, group_id: String) -> Result<bool, String> {
+        println!("disband_group called: group={}", group_id);
+        let me = self.my_node_id.clone().ok_or_else(|| "Node ID not initialized".to_string())?;
+
+        let conversation = self.conversations.get(&group_id)
+            .ok_or_else(|| "Group not found".to_string())?;
+        if !conversation.is_group || conversation.left {
+            return Err("Not an active group conversation".to_string());
+        }
+        if conversation.role_of(&me) != GroupRole::Owner {
+            return Err("Only the group owner can disband the group".to_string());
+        }
+
+        let (ops, before) = self.commit_membership_op(&group_id, MembershipAction::Disband, Vec::new())?;
+        self.group_invites.retain(|_, invite| invite.group_id != group_id);
+        let others: Vec<String> = before.iter()
+            .map(|m| m.node.clone())
+            .filter(|p| p != &me)
+            .collect();
+        self.send_membership_ops(&ops, &others);
+        self.announce_membership_changes(&group_id, &me, &before, &ops);
+        Ok(true)
+    }
+
+    // Change a group's name, description and avatar (admins only). The avatar is a file
+    // stored with upload_file; None removes it.
+    #[http]
+    async fn update_group_info(&mut self, group_id: String, group_name: String, description: String, avatar: Option<FileInfo>) -> Result<bool, String> {
+        println!("update_group_info called: group={}, name={}", group_id, group_name);
+        let me = self.my_node_id.clone().ok_or_else(|| "Node ID not initialized".to_string())?;
+
+        if group_name.trim().is_empty() {
+            return Err("Group name cannot be empty".to_string());
+        }
+        if avatar.as_ref().is_some_and(|a| !a.mime_type.starts_with("image/")) {
+            return Err("Group avatar must be an image".to_string());
+        }
+
+        let conversation = self.conversations.get(&group_id)
+            .ok_or_else(|| "Group not found".to_string())?;
+        if !conversation.is_group || conversation.left {
+            return Err("Not an active group conversation".to_string());
+        }
+        if !conversation.can_manage(&me) {
+            return Err("Only group admins can change group settings".to_string());
+        }
+        let others: Vec<String> = conversation.participants.iter()
+            .filter(|p| *p != &me)
+            .cloned()
+            .collect();
+
+        let update = GroupInfoUpdate {
+            group_id: group_id.clone(),
+            group_name: group_name.trim().to_string(),
+            description,
+            avatar,
+            announcement_only: conversation.announcement_only,
+            updated_at: Utc::now().to_rfc3339(),
         };
-        let request_wrapper = serde_json::json!({
-            "HandleGroupJoin": notification
-        });
-        let request_body = serde_json::to_vec(&request_wrapper)
-            .map_err(|e| format!("Serialization error: {}", e))?;
-        
-        let _ = Request::new()
-            .target(target_address)
-            .body(request_body)
-            .expects_response(30)
-            .send();
-        
+        self.broadcast_group_info_update(&me, update, &others);
+        Ok(true)
+    }
+
+    // Turn announcement-only mode on or off (admins only). While on, only admins may post.
+    #[http]
+    async fn set_announcement_only(&mut self, group_id: String, enabled: bool) -> Result<bool, String> {
+        println!("set_announcement_only called: group={}, enabled={}", group_id, enabled);
+        let me = self.my_node_id.clone().ok_or_else(|| "Node ID not initialized".to_string())?;
+
+        let conversation = self.conversations.get(&group_id)
+            .ok_or_else(|| "Group not found".to_string())?;
+        if !conversation.is_group || conversation.left {
+            return Err("Not an active group conversation".to_string());
+        }
+        if !conversation.can_manage(&me) {
+            return Err("Only group admins can change group settings".to_string());
+        }
+        let others: Vec<String> = conversation.participants.iter()
+            .filter(|p| *p != &me)
+            .cloned()
+            .collect();
+
+        let update = GroupInfoUpdate {
+            announcement_only: enabled,
+            updated_at: Utc::now().to_rfc3339(),
+            ..conversation.group_info()
+        };
+        self.broadcast_group_info_update(&me, update, &others);
+        Ok(true)
+    }
+
+    // Handle a name, description or avatar change made by a group admin
+    #[remote]
+    async fn handle_group_info_update(&mut self, update: GroupInfoUpdate) -> Result<bool, String> {
+        println!("Received group info update for group: {}", update.group_id);
+
+        let from = authenticated_sender()?;
+        let conversation = self.conversations.get(&update.group_id)
+            .ok_or_else(|| retry_later("Group not found"))?;
+        if !conversation.is_group || !conversation.can_manage(&from) {
+            println!("Rejected group info update for {}: {} is not an admin", update.group_id, from);
+            return Err("Only group admins can change group settings".to_string());
+        }
+        if update.group_name.trim().is_empty() {
+            return Err("Group name cannot be empty".to_string());
+        }
+        if update.avatar.as_ref().is_some_and(|a| !self.accepts_remote_file(&a.file_id, &update.group_id)) {
+            println!("Rejected group info update for {}: refused avatar file ID", update.group_id);
+            return Err("Avatar cannot be shared in this group".to_string());
+        }
+
+        self.apply_group_info_update(&from, update);
+        Ok(true)
+    }
+
+    // Create an invite code for a group (admins only). Anyone with the code can join
+    // until it expires or has been used `max_uses` times.
+    #[http]
+    async fn create_group_invite(&mut self, group_id: String, expires_in_secs: Option<u64>, max_uses: Option<u32>) -> Result<GroupInvite, String> {
+        println!("create_group_invite called: group={}, expires_in={:?}, max_uses={:?}", group_id, expires_in_secs, max_uses);
+        let me = self.my_node_id.clone().ok_or_else(|| "Node ID not initialized".to_string())?;
+
+        let conversation = self.conversations.get(&group_id)
+            .ok_or_else(|| "Group not found".to_string())?;
+        if !conversation.is_group || conversation.left {
+            return Err("Not an active group conversation".to_string());
+        }
+        if !conversation.can_manage(&me) {
+            return Err("Only group admins can create invites".to_string());
+        }
+        if max_uses == Some(0) {
+            return Err("An invite must allow at least one use".to_string());
+        }
+
+        let now = Utc::now();
+        let expires_at = match expires_in_secs {
+            Some(secs) => {
+                let expires_at = i64::try_from(secs).ok()
+                    .and_then(chrono::TimeDelta::try_seconds)
+                    .and_then(|delta| now.checked_add_signed(delta))
+                    .ok_or_else(|| "Invite expiry is too far in the future".to_string())?;
+                Some(expires_at.to_rfc3339())
+            },
+            None => None,
+        };
+        let token = Uuid::new_v4().to_string();
+        let invite = GroupInvite {
+            token: token.clone(),
+            group_id,
+            code: format!("{}/{}", me, token),
+            created_at: now.to_rfc3339(),
+            expires_at,
+            max_uses,
+            uses: 0,
+        };
+        self.group_invites.insert(token, invite.clone());
+        Ok(invite)
+    }
+
+    // Join a group with an invite code by asking the issuing node to admit us
+    #[http]
+    async fn join_group_with_invite(&mut self, invite_code: String) -> Result<String, String> {
+        println!("join_group_with_invite called: code={}", invite_code);
+
+        let (issuer, token) = invite_code.trim().split_once('/')
+            .filter(|(issuer, token)| !issuer.is_empty() && !token.is_empty())
+            .ok_or_else(|| "Invalid invite code".to_string())?;
+        if issuer == our().node {
+            return Err("This invite was issued by your own node".to_string());
+        }
+
+        let issuer_address = samchat_address(issuer)?;
+        let request = json!({ "RedeemGroupInvite": token });
+        match send::<Result<String, String>>(&request, &issuer_address, REMOTE_TIMEOUT_SECS).await {
+            // The group itself arrives as a join notification from the issuer
+            SendResult::Success(result) => result,
+            SendResult::Timeout => Err("The inviting node did not respond".to_string()),
+            SendResult::Offline => Err("The inviting node is offline".to_string()),
+            SendResult::DeserializationError(e) => Err(format!("Unexpected response from the inviting node: {}", e)),
+        }
+    }
+
+    // Admit the requesting node to a group if it holds a valid invite token we issued
+    #[remote]
+    async fn redeem_group_invite(&mut self, token: String) -> Result<String, String> {
+        let from = authenticated_sender()?;
+        let me = our().node.clone();
+        println!("Invite redemption from {}", from);
+
+        let invite = self.group_invites.get(&token)
+            .ok_or_else(|| "Invite not found".to_string())?;
+        if invite.expires_at.as_deref().is_some_and(|expires_at| is_due(expires_at, Utc::now())) {
+            return Err("Invite has expired".to_string());
+        }
+        let group_id = invite.group_id.clone();
+        let used_up = invite.max_uses.is_some_and(|max_uses| invite.uses >= max_uses);
+        let conversation = self.conversations.get(&group_id)
+            .ok_or_else(|| "Group not found".to_string())?;
+        if conversation.left || !conversation.can_manage(&me) {
+            return Err("Invite is no longer valid".to_string());
+        }
+
+        if conversation.participants.contains(&from) {
+            // Already a member; resend the group in case their copy is missing
+            self.send_group_join(&group_id, &from)?;
+            return Ok(group_id);
+        }
+        if used_up {
+            return Err("Invite has been used up".to_string());
+        }
+
+        self.admit_group_member(&group_id, &from)?;
+        if let Some(invite) = self.group_invites.get_mut(&token) {
+            invite.uses += 1;
+        }
+        Ok(group_id)
+    }
+
+    // Ask an admin of a group to add us
+    #[http]
+    async fn request_to_join_group(&mut self, admin_node: String, group_id: String, message: String) -> Result<bool, String> {
+        println!("request_to_join_group called: admin={}, group={}", admin_node, group_id);
+        if admin_node == our().node {
+            return Err("Cannot send a join request to your own node".to_string());
+        }
+        if self.conversations.get(&group_id).is_some_and(|c| !c.left) {
+            return Err("You are already a member of this group".to_string());
+        }
+
+        let request = GroupJoinRequest { group_id, message };
+        self.enqueue_remote(&admin_node, json!({
+            "HandleJoinRequest": request
+        }));
+        Ok(true)
+    }
+
+    // Handle a request to join a group we administer
+    #[remote]
+    async fn handle_join_request(&mut self, request: GroupJoinRequest) -> Result<bool, String> {
+        let from = authenticated_sender()?;
+        let me = our().node.clone();
+        println!("Received join request for group {} from {}", request.group_id, from);
+
+        let conversation = self.conversations.get_mut(&request.group_id)
+            .ok_or_else(|| "Group not found".to_string())?;
+        if !conversation.is_group || conversation.left || !conversation.can_manage(&me) {
+            return Err("This node cannot approve requests for the group".to_string());
+        }
+        if conversation.participants.contains(&from) {
+            return Err("Already a member of this group".to_string());
+        }
+
+        let join_request = JoinRequest {
+            node: from.clone(),
+            message: request.message,
+            requested_at: Utc::now().to_rfc3339(),
+        };
+        if let Some(position) = conversation.pending_requests.iter().position(|r| r.node == from) {
+            // Asking again just refreshes the request
+            conversation.pending_requests[position] = join_request.clone();
+        } else if conversation.pending_requests.len() >= MAX_PENDING_JOIN_REQUESTS {
+            return Err("Too many pending join requests for this group".to_string());
+        } else {
+            conversation.pending_requests.push(join_request.clone());
+            self.record_system_event(&request.group_id, &from, format!("{} asked to join the group", from));
+        }
+
+        let event = WsEvent::JoinRequested {
+            conversation_id: request.group_id.clone(),
+            request: join_request,
+        };
+        self.push_where(event, |s| s.watches(&request.group_id));
+        Ok(true)
+    }
+
+    // Approve a pending join request, adding the node like add_group_member would
+    #[http]
+    async fn approve_join_request(&mut self, group_id: String, node: String) -> Result<bool, String> {
+        println!("approve_join_request called: group={}, node={}", group_id, node);
+        self.check_join_request(&group_id, &node)?;
+        let already_member = self.conversations.get(&group_id)
+            .is_some_and(|c| c.participants.contains(&node));
+        if !already_member {
+            self.admit_group_member(&group_id, &node)?;
+        }
+        // Only settled once the node is in, so a failed admit can be retried
+        self.remove_join_request(&group_id, &node);
+        let event = WsEvent::JoinRequestResolved { conversation_id: group_id.clone(), node, approved: true };
+        self.push_where(event, |s| s.watches(&group_id));
+        Ok(true)
+    }
+
+    // Deny a pending join request
+    #[http]
+    async fn deny_join_request(&mut self, group_id: String, node: String) -> Result<bool, String> {
+        println!("deny_join_request called: group={}, node={}", group_id, node);
+        self.check_join_request(&group_id, &node)?;
+        self.remove_join_request(&group_id, &node);
+        let event = WsEvent::JoinRequestResolved { conversation_id: group_id.clone(), node, approved: false };
+        self.push_where(event, |s| s.watches(&group_id));
+        Ok(true)
+    }
+
+    // Leave a group: notify the remaining members and keep the history read-only
+    #[http]
+    async fn leave_group(&mut self, group_id: String) -> Result<bool, String> {
+        println!("leave_group called: group={}", group_id);
+        let me = self.my_node_id.clone().ok_or_else(|| "Node ID not initialized".to_string())?;
+
+        let conversation = self.conversations.get(&group_id)
+            .ok_or_else(|| "Group not found".to_string())?;
+        if !conversation.is_group {
+            return Err("Not a group conversation".to_string());
+        }
+        if conversation.left {
+            return Err("You have already left this group".to_string());
+        }
+        if conversation.role_of(&me) == GroupRole::Owner && conversation.participants.len() > 1 {
+            return Err("Transfer ownership or disband the group before leaving".to_string());
+        }
+
+        // Only the creator can start a membership log, so other members of a group without
+        // one leave by notifying the remaining members instead
+        if conversation.membership_log.is_empty() && conversation.created_by.as_deref() != Some(me.as_str()) {
+            let remaining: Vec<String> = conversation.participants.iter()
+                .filter(|p| *p != &me)
+                .cloned()
+                .collect();
+            if let Some(conversation) = self.conversations.get_mut(&group_id) {
+                conversation.participants.retain(|p| p != &me);
+                conversation.roles.remove(&me);
+                conversation.left = true;
+                conversation.last_updated = Utc::now().to_rfc3339();
+            }
+            for participant in &remaining {
+                self.enqueue_remote(participant, json!({
+                    "HandleGroupLeave": GroupLeaveNotification {
+                        group_id: group_id.clone(),
+                        member: me.clone(),
+                    }
+                }));
+            }
+            let event = WsEvent::GroupMemberLeft {
+                conversation_id: group_id.clone(),
+                member: me.clone(),
+            };
+            self.push_where(event, |s| s.watches(&group_id));
+            self.record_system_event(&group_id, &me, format!("{} left the group", me));
+            return Ok(true);
+        }
+
+        let leaving = GroupMember { node: me.clone(), role: conversation.role_of(&me) };
+        let (ops, before) = self.commit_membership_op(&group_id, MembershipAction::Leave, vec![leaving])?;
+        if let Some(conversation) = self.conversations.get_mut(&group_id) {
+            conversation.left = true;
+        }
+
+        let remaining: Vec<String> = before.iter()
+            .map(|m| m.node.clone())
+            .filter(|p| p != &me)
+            .collect();
+        self.send_membership_ops(&ops, &remaining);
+        self.announce_membership_changes(&group_id, &me, &before, &ops);
         Ok(true)
     }
 
@@ -649,41 +1709,62 @@ impl SamchatState {
     async fn receive_message(&mut self, message: ChatMessage) -> Result<bool, String> {
         println!("receive_message called: from={}, content='{}'", message.sender, message.content);
 
+        let from = authenticated_sender()?;
+        if from != message.sender {
+            println!("Rejected message {}: claims sender {} but came from {}", message.id, message.sender, from);
+            return Err("Message sender does not match the requesting node".to_string());
+        }
+
+        if message.kind != MessageKind::User {
+            return Err("System events cannot be sent between nodes".to_string());
+        }
+
         let conversation_id = message.conversation_id.clone();
+        let me = our().node.clone();
+        if message.file_info.as_ref().is_some_and(|f| !self.accepts_remote_file(&f.file_id, &conversation_id)) {
+            println!("Rejected message {} from {}: refused file ID", message.id, from);
+            return Err("File cannot be shared in this conversation".to_string());
+        }
+
+        if message.recipients.is_some() {
+            if self.conversations.get(&conversation_id).is_some_and(|c| c.disbanded) {
+                println!("Rejected message {}: group {} has been disbanded", message.id, conversation_id);
+                return Err("This group has been disbanded".to_string());
+            }
+            // Group messages are only accepted from current members of a group we know about.
+            // The group or the sender's membership may simply not have reached us yet.
+            match self.conversations.get(&conversation_id) {
+                Some(c) if !c.is_group || c.left => {
+                    println!("Rejected message {}: not a member of {}", message.id, conversation_id);
+                    return Err("Not a member of this group".to_string());
+                },
+                Some(c) if c.participants.contains(&from) => {},
+                _ => {
+                    println!("Postponed message {}: {} is not yet a known participant of {}", message.id, from, conversation_id);
+                    return Err(retry_later("Sender is not a participant of this group"));
+                },
+            }
+            if self.conversations.get(&conversation_id).is_some_and(|c| c.announcement_only && !c.can_manage(&from)) {
+                println!("Dropped message {}: {} is not an admin of announcement-only group {}", message.id, from, conversation_id);
+                return Err("Only admins can post in this group".to_string());
+            }
+        } else {
+            // Direct messages must be addressed to us in the conversation between the two nodes
+            let mut participants = vec![from.clone(), me.clone()];
+            participants.sort();
+            if message.recipient.as_ref() != Some(&me) || conversation_id != participants.join("|") {
+                println!("Rejected message {}: not a direct message from {} to us", message.id, from);
+                return Err("Direct message is not addressed to this node".to_string());
+            }
+        }
         
         // Current time as RFC3339 string
         let current_time_str = Utc::now().to_rfc3339();
 
          // Persist received message
-        let conversation = self.conversations.entry(conversation_id.clone()).or_insert_with(|| {
-            // If conversation doesn't exist, create it based on message
-            if message.recipients.is_some() {
-                // This is a group message, but we don't have the group info
-                // This shouldn't happen in normal flow, but handle it gracefully
-                Conversation {
-                    id: conversation_id.clone(),
-                    participants: vec![message.sender.clone()], // Add more as we discover them
-                    messages: Vec::new(),
-                    last_updated: message.timestamp.clone(),
-                    is_group: true,
-                    group_name: None,
-                    created_by: None,
-                }
-            } else {
-                // Direct message
-                let mut participants = vec![message.sender.clone(), message.recipient.clone().unwrap_or_default()];
-                participants.sort();
-                Conversation {
-                    id: conversation_id.clone(),
-                    participants: participants,
-                    messages: Vec::new(),
-                    last_updated: message.timestamp.clone(),
-                    is_group: false,
-                    group_name: None,
-                    created_by: None,
-                }
-            }
-        });
+        // Only direct conversations are created on demand; groups were checked above
+        let conversation = self.conversations.entry(conversation_id.clone())
+            .or_insert_with(|| Conversation::direct(&conversation_id, &from, &me, &message.timestamp));
 
         // Avoid adding duplicates (simple check based on ID)
         if !conversation.messages.iter().any(|m| m.id == message.id) {
@@ -693,72 +1774,585 @@ impl SamchatState {
             conversation.messages.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
             conversation.last_updated = current_time_str; // Update with current time string
             println!("Message {} received and persisted.", message.id);
+            if let Some(file_info) = &message.file_info {
+                self.record_shared_file(&file_info.file_id, &conversation_id);
+            }
+            self.push_where(WsEvent::NewMessage { message: message.clone() }, |s| s.watches(&conversation_id));
         } else {
              println!("Duplicate message {} received, ignoring.", message.id);
         }
 
-        // No explicit response needed for fire-and-forget remote calls generally.
+        // Acknowledge delivery to the sender (duplicates too, in case our earlier ack was lost)
+        let ack = DeliveryAck {
+            conversation_id,
+            message_id: message.id.clone(),
+            recipient: our().node.clone(),
+        };
+        self.enqueue_remote(&message.sender, json!({
+            "HandleDeliveryAck": ack
+        }));
+
         Ok(true)
     }
-    
-    // Handle group join notification
+
+    // Handle delivery acknowledgement for a message we sent
     #[remote]
-    async fn handle_group_join(&mut self, notification: GroupJoinNotification) -> Result<bool, String> {
-        println!("Received group join notification for group: {}", notification.group_id);
-        
-        let current_time_str = Utc::now().to_rfc3339();
-        
-        // Create or update the group conversation
-        let conversation = Conversation {
-            id: notification.group_id.clone(),
-            participants: notification.participants,
-            messages: Vec::new(),
-            last_updated: current_time_str,
-            is_group: true,
-            group_name: Some(notification.group_name),
-            created_by: Some(notification.created_by),
+    async fn handle_delivery_ack(&mut self, ack: DeliveryAck) -> Result<bool, String> {
+        println!("Received delivery ack for message {} from {}", ack.message_id, ack.recipient);
+
+        let from = authenticated_sender()?;
+        if from != ack.recipient {
+            println!("Rejected delivery ack for {}: claims {} but came from {}", ack.message_id, ack.recipient, from);
+            return Err("Ack recipient does not match the requesting node".to_string());
+        }
+
+        let conversation = self.conversations.get_mut(&ack.conversation_id)
+            .ok_or_else(|| "Conversation not found".to_string())?;
+        let message = conversation.messages.iter_mut()
+            .find(|m| m.id == ack.message_id)
+            .ok_or_else(|| "Message not found".to_string())?;
+
+        // Only the message's recipients can acknowledge it, and it counts as delivered
+        // once all of them have
+        let expected: Vec<String> = match (&message.recipients, &message.recipient) {
+            (Some(recipients), _) => recipients.clone(),
+            (None, Some(recipient)) => vec![recipient.clone()],
+            (None, None) => Vec::new(),
         };
-        
-        self.conversations.insert(notification.group_id, conversation);
+        if !expected.contains(&from) {
+            println!("Rejected delivery ack for {}: {} is not a recipient", ack.message_id, from);
+            return Err("Sender is not a recipient of this message".to_string());
+        }
+        if !message.delivered_to.contains(&ack.recipient) {
+            message.delivered_to.push(ack.recipient);
+        }
+        message.delivered = expected.iter().all(|r| message.delivered_to.contains(r));
+
+        let event = WsEvent::DeliveryUpdate {
+            conversation_id: ack.conversation_id.clone(),
+            message_id: ack.message_id.clone(),
+            delivered: message.delivered,
+            delivered_to: message.delivered_to.clone(),
+        };
+        self.push_where(event, |s| s.watches(&ack.conversation_id));
         Ok(true)
     }
     
-    // Handle group leave notification
+    // Handle group join notification. A notification for a group we already have is merged
+    // into it, so re-sent or repeated notifications never discard history.
     #[remote]
-    async fn handle_group_leave(&mut self, notification: GroupLeaveNotification) -> Result<bool, String> {
-        println!("Received group leave notification for group: {}, member: {}", notification.group_id, notification.member);
-        
-        if let Some(conversation) = self.conversations.get_mut(&notification.group_id) {
-            conversation.participants.retain(|p| p != &notification.member);
-            conversation.last_updated = Utc::now().to_rfc3339();
+    async fn handle_group_join(&mut self, notification: GroupJoinNotification) -> Result<bool, String> {
+        println!("Received group join notification for group: {}", notification.group_id);
+
+        let from = authenticated_sender()?;
+        let me = our().node.clone();
+        let valid = notification.membership_log.iter()
+            .all(|op| op.group_id == notification.group_id && verify_membership_op(op));
+        if !valid {
+            println!("Rejected join notification for {}: invalid membership log", notification.group_id);
+            return Err("Invalid membership log signature".to_string());
+        }
+        // Once a group has a membership log, only signed ops change its membership
+        let has_log = self.conversations.get(&notification.group_id).is_some_and(|c| !c.membership_log.is_empty());
+        if has_log && notification.membership_log.is_empty() {
+            println!("Rejected join notification for {} from {}: no membership log", notification.group_id, from);
+            return Err("Membership of this group only changes through signed membership ops".to_string());
         }
-        Ok(true)
-    }
 
-    // Get all conversations
-    #[http]
-    async fn get_conversations(&self, _request_body: String) -> Result<Vec<ConversationSummary>, String> {
-         println!("get_conversations called");
-         let summaries: Vec<ConversationSummary> = self.conversations.values()
-            .map(|conv| ConversationSummary {
-                id: conv.id.clone(),
-                participants: conv.participants.clone(),
-                last_updated: conv.last_updated.clone(), // Already a string
-                is_group: conv.is_group,
-                group_name: conv.group_name.clone(),
-            })
-            .collect();
-        
-        // Sort summaries by last_updated (newest first)
-        // Reverse lexicographical sort for RFC3339 strings puts newest first
-        let mut sorted_summaries = summaries;
-        sorted_summaries.sort_by(|a, b| b.last_updated.cmp(&a.last_updated));
+        // The creator of a group we already have is never taken from a notification
+        let created_by = match self.conversations.get(&notification.group_id) {
+            Some(existing) => existing.created_by.clone(),
+            None => Some(notification.created_by.clone()),
+        };
+        let has_genesis = notification.membership_log.iter().any(|op| {
+            op.action == MembershipAction::Create && op.epoch == 0 && created_by.as_deref() == Some(op.author.as_str())
+        });
+        // With the genesis op, the signed log is the source of truth and the rest of the
+        // notification is only a hint. Without it, the notifier must be an admin.
+        if !has_genesis {
+            if let Some(existing) = self.conversations.get(&notification.group_id) {
+                if !existing.participants.contains(&from) {
+                    println!("Rejected join notification for {}: {} is not a known member", notification.group_id, from);
+                    return Err("Sender is not a participant of this group".to_string());
+                }
+                if !existing.can_manage(&from) {
+                    println!("Rejected join notification for {}: {} is not an admin", notification.group_id, from);
+                    return Err("Only group admins can add members".to_string());
+                }
+            } else {
+                // New group: the notifier must be an admin according to the group it describes
+                let from_role = notification.roles.iter()
+                    .find(|m| m.node == from)
+                    .map(|m| m.role)
+                    .unwrap_or(if notification.created_by == from { GroupRole::Owner } else { GroupRole::Member });
+                if from_role == GroupRole::Member {
+                    println!("Rejected join notification for {}: {} is not an admin", notification.group_id, from);
+                    return Err("Only group admins can add members".to_string());
+                }
+            }
+        }
+        if self.conversations.get(&notification.group_id).is_some_and(|c| !c.is_group) {
+            return Err("Not a group conversation".to_string());
+        }
         
-        Ok(sorted_summaries)
-    }
+        let current_time_str = Utc::now().to_rfc3339();
 
-    // Get all messages for a specific conversation
-    #[http]
+        // The group as the notification describes it
+        let mut incoming = Conversation::group(&notification.group_id, notification.participants, &notification.group_name, created_by, &current_time_str);
+        incoming.roles = notification.roles.into_iter()
+            .filter(|m| m.role != GroupRole::Member)
+            .map(|m| (m.node, m.role))
+            .collect();
+        incoming.merge_membership_ops(notification.membership_log);
+        // For a group we already have, judge the notifier by our log merged with its ops,
+        // not by the membership the notification claims
+        if let Some(existing) = self.conversations.get(&notification.group_id) {
+            if existing.membership_log.is_empty() {
+                if !existing.participants.contains(&from) {
+                    println!("Rejected join notification for {}: {} is not a known member", notification.group_id, from);
+                    return Err("Sender is not a participant of this group".to_string());
+                }
+            } else {
+                let mut merged = incoming.clone();
+                merged.merge_membership_ops(existing.membership_log.clone());
+                if !merged.participants.contains(&from) {
+                    println!("Rejected join notification for {}: {} is not a member", notification.group_id, from);
+                    return Err("Sender is not a participant of this group".to_string());
+                }
+            }
+        }
+
+        if incoming.disbanded && !self.conversations.contains_key(&notification.group_id) {
+            return Err("This group has been disbanded".to_string());
+        }
+
+        // Only a member of the group may tell us about it, and only if it includes us
+        if !incoming.participants.contains(&from) || !incoming.participants.contains(&me) {
+            println!("Rejected join notification for {} from {}", notification.group_id, from);
+            return Err("Join notification must come from a member and include this node".to_string());
+        }
+        // A group we don't have yet can only be introduced by one of its admins
+        if has_genesis && !self.conversations.contains_key(&notification.group_id) && !incoming.can_manage(&from) {
+            println!("Rejected join notification for {}: {} is not an admin", notification.group_id, from);
+            return Err("Only group admins can add members".to_string());
+        }
+
+        let group_id = notification.group_id.clone();
+        let Some(existing) = self.conversations.get_mut(&group_id) else {
+            // Description and avatar are only taken from admins
+            if let Some(info) = notification.info.filter(|_| incoming.can_manage(&from)) {
+                incoming.apply_group_info(&from, &info);
+            }
+            if incoming.group_avatar.as_ref().is_some_and(|a| !self.accepts_remote_file(&a.file_id, &group_id)) {
+                println!("Dropped avatar of group {} from {}: refused file ID", group_id, from);
+                incoming.group_avatar = None;
+            }
+            let event = WsEvent::GroupJoined {
+                conversation_id: group_id.clone(),
+                group_name: incoming.group_name.clone().unwrap_or_default(),
+                participants: incoming.participants.clone(),
+            };
+            let content = format!("{} added you to \"{}\"", from, incoming.group_name.clone().unwrap_or_default());
+            if let Some(avatar) = &incoming.group_avatar {
+                self.record_shared_file(&avatar.file_id, &group_id);
+            }
+            self.conversations.insert(group_id.clone(), incoming);
+            self.push_where(event, |s| s.watches(&group_id));
+            self.record_system_event(&group_id, &from, content);
+            return Ok(true);
+        };
+
+        // Merge into the group we already have, keeping its messages and read pointers.
+        // Its creator never changes, and its name and settings only change through an
+        // admin's group info below.
+        let was_left = existing.left;
+        let before = existing.member_roles();
+        let new_ops = if incoming.membership_log.is_empty() {
+            // Neither side has a log (checked above), so the notifier is an admin. It cannot
+            // grant more than its own authority: only the owner assigns roles, nobody else
+            // becomes owner, and admins cannot remove the owner or other admins.
+            let from_is_owner = existing.role_of(&from) == GroupRole::Owner;
+            let mut participants = incoming.participants;
+            for node in &existing.participants {
+                let protected = match existing.role_of(node) {
+                    GroupRole::Owner => true,
+                    GroupRole::Admin => !from_is_owner && node != &from,
+                    GroupRole::Member => false,
+                };
+                if protected && !participants.contains(node) {
+                    participants.push(node.clone());
+                }
+            }
+            let mut roles: HashMap<String, GroupRole> = if from_is_owner {
+                incoming.roles.into_iter()
+                    .filter(|(node, role)| *role != GroupRole::Owner || existing.created_by.as_deref() == Some(node.as_str()))
+                    .collect()
+            } else {
+                existing.roles.clone()
+            };
+            roles.retain(|node, _| participants.contains(node));
+            existing.participants = participants;
+            existing.roles = roles;
+            Vec::new()
+        } else {
+            existing.merge_membership_ops(incoming.membership_log)
+        };
+        existing.left = existing.disbanded || !existing.participants.contains(&me);
+        let info = notification.info.filter(|_| existing.can_manage(&from));
+
+        if was_left && !existing.left {
+            let event = WsEvent::GroupJoined {
+                conversation_id: group_id.clone(),
+                group_name: existing.group_name.clone().unwrap_or_default(),
+                participants: existing.participants.clone(),
+            };
+            self.push_where(event, |s| s.watches(&group_id));
+        }
+        self.announce_membership_changes(&group_id, &from, &before, &new_ops);
+        let info = info.filter(|i| match &i.avatar {
+            Some(avatar) => self.accepts_remote_file(&avatar.file_id, &group_id),
+            None => true,
+        });
+        if let Some(info) = info {
+            self.apply_group_info_update(&from, info);
+        }
+        Ok(true)
+    }
+    
+    // Handle group leave notification (groups without a membership log only)
+    #[remote]
+    async fn handle_group_leave(&mut self, notification: GroupLeaveNotification) -> Result<bool, String> {
+        println!("Received group leave notification for group: {}, member: {}", notification.group_id, notification.member);
+
+        // Members can only leave on their own behalf
+        let from = authenticated_sender()?;
+        if from != notification.member {
+            println!("Rejected leave notification for {}: claims {} but came from {}", notification.group_id, notification.member, from);
+            return Err("Leaving member does not match the requesting node".to_string());
+        }
+        
+        if let Some(conversation) = self.conversations.get_mut(&notification.group_id) {
+            if !conversation.membership_log.is_empty() {
+                return Err("Membership of this group only changes through signed membership ops".to_string());
+            }
+            if conversation.participants.contains(&notification.member) {
+                conversation.participants.retain(|p| p != &notification.member);
+                conversation.roles.remove(&notification.member);
+                conversation.last_updated = Utc::now().to_rfc3339();
+                let event = WsEvent::GroupMemberLeft {
+                    conversation_id: notification.group_id.clone(),
+                    member: notification.member.clone(),
+                };
+                self.push_where(event, |s| s.watches(&notification.group_id));
+                self.record_system_event(&notification.group_id, &from, format!("{} left the group", from));
+            }
+        }
+        Ok(true)
+    }
+
+    // Handle signed membership ops from another member. Ops can arrive late or out of
+    // order; if we are missing epochs we first fetch them from the sender.
+    #[remote]
+    async fn handle_membership_ops(&mut self, ops: Vec<MembershipOp>) -> Result<bool, String> {
+        let from = authenticated_sender()?;
+        let me = our().node.clone();
+        let group_id = ops.first()
+            .map(|op| op.group_id.clone())
+            .ok_or_else(|| "No membership ops".to_string())?;
+        println!("Received {} membership ops for group {} from {}", ops.len(), group_id, from);
+
+        if ops.iter().any(|op| op.group_id != group_id || !verify_membership_op(op)) {
+            println!("Rejected membership ops for {} from {}: invalid signature", group_id, from);
+            return Err("Invalid membership op signature".to_string());
+        }
+        let conversation = self.conversations.get(&group_id)
+            .ok_or_else(|| retry_later("Group not found"))?;
+        if !conversation.is_group {
+            return Err("Not a group conversation".to_string());
+        }
+        if !conversation.participants.contains(&from) {
+            println!("Postponed membership ops for {}: {} is not yet a known member", group_id, from);
+            return Err(retry_later("Sender is not a participant of this group"));
+        }
+
+        let mut ops = ops;
+        let local_epoch = conversation.epoch();
+        let lowest_epoch = ops.iter().map(|op| op.epoch).min().unwrap_or(0);
+        if lowest_epoch > local_epoch + 1 {
+            println!("Missing epochs {}..{} for {}, reconciling with {}", local_epoch + 1, lowest_epoch, group_id, from);
+            ops.extend(fetch_membership_log(&from, &group_id, local_epoch).await);
+        }
+
+        let conversation = self.conversations.get_mut(&group_id)
+            .ok_or_else(|| "Group not found".to_string())?;
+        let before = conversation.member_roles();
+        let was_member = conversation.participants.contains(&me);
+        let new_ops = conversation.merge_membership_ops(ops);
+        if new_ops.is_empty() {
+            return Ok(true);
+        }
+        conversation.last_updated = Utc::now().to_rfc3339();
+        if was_member && !conversation.participants.contains(&me) {
+            // We are no longer a member; keep the history read-only
+            conversation.left = true;
+        }
+        if conversation.disbanded {
+            println!("Group {} was disbanded", group_id);
+        }
+
+        self.announce_membership_changes(&group_id, &from, &before, &new_ops);
+        Ok(true)
+    }
+
+    // Serve a group's membership ops from `since_epoch` on, so members that missed
+    // changes can catch up
+    #[remote]
+    async fn get_membership_log(&self, group_id: String, since_epoch: u64) -> Result<Vec<MembershipOp>, String> {
+        let from = authenticated_sender()?;
+        let conversation = self.conversations.get(&group_id)
+            .ok_or_else(|| "Group not found".to_string())?;
+
+        // Current and former members may read the log
+        let known = conversation.participants.contains(&from)
+            || conversation.membership_log.iter().any(|op| op.author == from || op.members.iter().any(|m| m.node == from));
+        if !known {
+            println!("Rejected membership log request for {} from {}", group_id, from);
+            return Err(format!("Forbidden: {} is not a member of {}", from, group_id));
+        }
+
+        Ok(conversation.membership_log.iter()
+            .filter(|op| op.epoch >= since_epoch)
+            .cloned()
+            .collect())
+    }
+
+    // Mark a conversation as read up to a message and tell the other participants
+    #[http]
+    async fn mark_read(&mut self, conversation_id: String, up_to_message_id: String) -> Result<bool, String> {
+        println!("mark_read called: conversation={}, up_to={}", conversation_id, up_to_message_id);
+        let reader = self.my_node_id.clone().ok_or_else(|| "Node ID not initialized".to_string())?;
+
+        let conversation = self.conversations.get_mut(&conversation_id)
+            .ok_or_else(|| format!("Conversation with ID {} not found", conversation_id))?;
+        if !conversation.apply_read_receipt(&reader, &up_to_message_id)? {
+            // Already read at least this far, nothing new to propagate
+            return Ok(true);
+        }
+
+        // System events only exist on this node, so report the last real message instead
+        let up_to = conversation.messages.iter().position(|m| m.id == up_to_message_id).unwrap_or(0);
+        let Some(shared_up_to) = conversation.messages[..=up_to].iter()
+            .rev()
+            .find(|m| m.kind == MessageKind::User)
+            .map(|m| m.id.clone())
+        else {
+            return Ok(true);
+        };
+
+        let others: Vec<String> = conversation.participants.iter()
+            .filter(|p| *p != &reader)
+            .cloned()
+            .collect();
+        for participant in others {
+            let receipt = ReadReceipt {
+                conversation_id: conversation_id.clone(),
+                reader: reader.clone(),
+                up_to_message_id: shared_up_to.clone(),
+            };
+            self.enqueue_remote(&participant, json!({
+                "HandleReadReceipt": receipt
+            }));
+        }
+        Ok(true)
+    }
+
+    // Handle read receipt from another participant
+    #[remote]
+    async fn handle_read_receipt(&mut self, receipt: ReadReceipt) -> Result<bool, String> {
+        println!("Received read receipt from {} for conversation {}", receipt.reader, receipt.conversation_id);
+
+        let from = authenticated_sender()?;
+        if from != receipt.reader {
+            println!("Rejected read receipt: claims reader {} but came from {}", receipt.reader, from);
+            return Err("Reader does not match the requesting node".to_string());
+        }
+
+        let conversation = self.conversations.get_mut(&receipt.conversation_id)
+            .ok_or_else(|| retry_later("Conversation not found"))?;
+        if !conversation.participants.contains(&receipt.reader) {
+            return Err(retry_later("Reader is not a participant"));
+        }
+        if conversation.apply_read_receipt(&receipt.reader, &receipt.up_to_message_id)? {
+            let event = WsEvent::ReadUpdate {
+                conversation_id: receipt.conversation_id.clone(),
+                reader: receipt.reader.clone(),
+                up_to_message_id: receipt.up_to_message_id.clone(),
+            };
+            self.push_where(event, |s| s.watches(&receipt.conversation_id));
+        }
+        Ok(true)
+    }
+
+    // Attempt every due outbox entry, then wait for the next one to become due
+    #[local]
+    async fn retry_outbox(&mut self, _request_body: String) -> Result<bool, String> {
+        let now = Utc::now();
+        let due: Vec<String> = self.outbox.iter()
+            .filter(|e| !e.in_flight && !e.failed && is_due(&e.next_attempt_at, now))
+            .map(|e| e.id.clone())
+            .collect();
+
+        // Targets that failed this round, with the time their next attempt is due
+        let mut unreachable: HashMap<String, String> = HashMap::new();
+        for entry_id in due {
+            // Another retry round may have picked this entry up while we were awaiting
+            let Some(entry) = self.outbox.iter_mut().find(|e| e.id == entry_id && !e.in_flight && !e.failed) else {
+                continue;
+            };
+            // Don't wait out another timeout on a node that just failed; try again with its first entry
+            if let Some(next_attempt_at) = unreachable.get(&entry.target_node) {
+                entry.next_attempt_at = next_attempt_at.clone();
+                continue;
+            }
+            entry.in_flight = true;
+            let target_node = entry.target_node.clone();
+            let request = entry.request.clone();
+
+            let result = deliver_remote(&target_node, &request).await;
+            let failed = result.is_err();
+            self.record_outbox_attempt(&entry_id, result);
+            if failed {
+                if let Some(entry) = self.outbox.iter().find(|e| e.id == entry_id) {
+                    unreachable.insert(target_node, entry.next_attempt_at.clone());
+                }
+            }
+        }
+
+        // Only one handler waits on the timer; it starts a fresh round when it wakes up
+        if self.outbox.iter().any(|e| !e.failed) && !self.outbox_retry_pending {
+            self.outbox_retry_pending = true;
+            let now = Utc::now();
+            let wait_ms = self.outbox.iter()
+                .filter(|e| !e.failed)
+                .filter_map(|e| chrono::DateTime::parse_from_rfc3339(&e.next_attempt_at).ok())
+                .map(|t| (t.with_timezone(&Utc) - now).num_milliseconds().max(0) as u64)
+                .min()
+                .unwrap_or(0)
+                .clamp(1000, OUTBOX_MAX_SLEEP_MS);
+            sleep_ms(wait_ms).await;
+            self.outbox_retry_pending = false;
+            self.kick_outbox();
+        }
+        Ok(true)
+    }
+
+    // Get the requests that are still waiting to be delivered, and those that gave up
+    #[http]
+    async fn get_outbox_status(&self, _request_body: String) -> Result<Vec<OutboxStatus>, String> {
+        println!("get_outbox_status called");
+        let statuses = self.outbox.iter()
+            .map(|entry| OutboxStatus {
+                id: entry.id.clone(),
+                target_node: entry.target_node.clone(),
+                request_kind: entry.request_kind(),
+                attempts: entry.attempts,
+                created_at: entry.created_at.clone(),
+                next_attempt_at: entry.next_attempt_at.clone(),
+                last_error: entry.last_error.clone(),
+                failed: entry.failed,
+            })
+            .collect();
+        Ok(statuses)
+    }
+
+    // Drop an outbox entry so it is never delivered
+    #[http]
+    async fn cancel_outbox_entry(&mut self, entry_id: String) -> Result<bool, String> {
+        println!("cancel_outbox_entry called: id={}", entry_id);
+        let before = self.outbox.len();
+        self.outbox.retain(|e| e.id != entry_id);
+        if self.outbox.len() == before {
+            return Err("Outbox entry not found".to_string());
+        }
+        Ok(true)
+    }
+
+    // Handle subscribe/unsubscribe messages from UI clients
+    #[ws]
+    fn websocket(&mut self, channel_id: u32, message_type: WsMessageType, blob: LazyLoadBlob) {
+        match message_type {
+            WsMessageType::Text | WsMessageType::Binary => {
+                let client_message = match serde_json::from_slice::<WsClientMessage>(blob.bytes()) {
+                    Ok(client_message) => client_message,
+                    Err(e) => {
+                        println!("Invalid WebSocket message on channel {}: {}", channel_id, e);
+                        return;
+                    }
+                };
+                let subscription = self.ws_subscriptions.entry(channel_id).or_default();
+                match client_message {
+                    WsClientMessage::Subscribe { conversation_ids } => {
+                        subscription.conversation_ids.extend(conversation_ids);
+                    },
+                    WsClientMessage::Unsubscribe { conversation_ids } => {
+                        for conversation_id in &conversation_ids {
+                            subscription.conversation_ids.remove(conversation_id);
+                        }
+                    },
+                    WsClientMessage::SubscribeAll => subscription.all = true,
+                    WsClientMessage::UnsubscribeAll => {
+                        subscription.all = false;
+                        subscription.conversation_ids.clear();
+                    },
+                }
+            },
+            WsMessageType::Close => {
+                self.ws_subscriptions.remove(&channel_id);
+            },
+            _ => {}
+        }
+    }
+
+    // Get all conversations
+    #[http]
+    async fn get_conversations(&self, _request_body: String) -> Result<Vec<ConversationSummary>, String> {
+         println!("get_conversations called");
+         let me = self.my_node_id.clone().unwrap_or_default();
+         let summaries: Vec<ConversationSummary> = self.conversations.values()
+            .map(|conv| {
+                let last_message = conv.messages.last();
+                ConversationSummary {
+                    id: conv.id.clone(),
+                    participants: conv.participants.clone(),
+                    last_updated: conv.last_updated.clone(), // Already a string
+                    is_group: conv.is_group,
+                    group_name: conv.group_name.clone(),
+                    group_description: conv.group_description.clone(),
+                    group_avatar: conv.group_avatar.clone(),
+                    announcement_only: conv.announcement_only,
+                    left: conv.left,
+                    disbanded: conv.disbanded,
+                    roles: if conv.is_group { conv.member_roles() } else { Vec::new() },
+                    pending_requests: conv.pending_requests.clone(),
+                    unread_count: conv.unread_count(&me),
+                    last_message_sender: last_message.map(|m| m.sender.clone()),
+                    last_message_preview: last_message.map(message_preview),
+                    last_message_timestamp: last_message.map(|m| m.timestamp.clone()),
+                    last_message_has_attachment: last_message.is_some_and(|m| m.file_info.is_some()),
+                }
+            })
+            .collect();
+        
+        // Sort summaries by last_updated (newest first)
+        // Reverse lexicographical sort for RFC3339 strings puts newest first
+        let mut sorted_summaries = summaries;
+        sorted_summaries.sort_by(|a, b| b.last_updated.cmp(&a.last_updated));
+        
+        Ok(sorted_summaries)
+    }
+
+    // Get all messages for a specific conversation
+    #[http]
     async fn get_messages(&self, conversation_id: String) -> Result<Vec<ChatMessage>, String> {
          println!("get_messages called for conversation: {}", conversation_id);
          match self.conversations.get(&conversation_id) {
@@ -766,4 +2360,1017 @@ impl SamchatState {
              None => Err(format!("Conversation with ID {} not found", conversation_id)),
          }
     }
+
+    // Get one page of messages around a cursor (a message ID or an RFC3339 timestamp).
+    // Without a cursor, Older starts from the newest message and Newer from the oldest.
+    #[http]
+    async fn get_messages_page(&self, conversation_id: String, cursor: Option<String>, direction: PageDirection, limit: u32) -> Result<MessagePage, String> {
+        println!("get_messages_page called: conversation={}, cursor={:?}, direction={:?}, limit={}", conversation_id, cursor, direction, limit);
+        let conversation = self.conversations.get(&conversation_id)
+            .ok_or_else(|| format!("Conversation with ID {} not found", conversation_id))?;
+        let messages = &conversation.messages;
+        let limit = match limit as usize {
+            0 => DEFAULT_PAGE_SIZE,
+            n => n.min(MAX_PAGE_SIZE),
+        };
+
+        let boundary = match &cursor {
+            Some(cursor) => Some(resolve_cursor(messages, cursor, &direction)?),
+            None => None,
+        };
+        let (start, end) = match direction {
+            PageDirection::Older => {
+                let end = boundary.unwrap_or(messages.len());
+                (end.saturating_sub(limit), end)
+            },
+            PageDirection::Newer => {
+                let start = boundary.unwrap_or(0);
+                (start, (start + limit).min(messages.len()))
+            },
+        };
+
+        if start >= end {
+            return Ok(MessagePage { messages: Vec::new(), next_cursor: None, prev_cursor: None });
+        }
+        let first_id = Some(messages[start].id.clone());
+        let last_id = Some(messages[end - 1].id.clone());
+        let (older_cursor, newer_cursor) = (
+            if start > 0 { first_id } else { None },
+            if end < messages.len() { last_id } else { None },
+        );
+        let (next_cursor, prev_cursor) = match direction {
+            PageDirection::Older => (older_cursor, newer_cursor),
+            PageDirection::Newer => (newer_cursor, older_cursor),
+        };
+
+        Ok(MessagePage {
+            messages: messages[start..end].to_vec(),
+            next_cursor,
+            prev_cursor,
+        })
+    }
+}
+
+// --- Outbox helpers ---
+impl SamchatState {
+    // Queue a request for the samchat process on `target_node` and trigger a delivery round
+    fn enqueue_remote(&mut self, target_node: &str, request: serde_json::Value) {
+        let now = Utc::now().to_rfc3339();
+        self.outbox.push(OutboxEntry {
+            id: Uuid::new_v4().to_string(),
+            target_node: target_node.to_string(),
+            request,
+            attempts: 0,
+            created_at: now.clone(),
+            next_attempt_at: now,
+            last_error: None,
+            failed: false,
+            in_flight: false,
+        });
+        self.kick_outbox();
+    }
+
+    // Ask ourselves to run retry_outbox as a separate handler
+    fn kick_outbox(&self) {
+        let request_body = serde_json::to_vec(&json!({ "RetryOutbox": "" })).unwrap();
+        let _ = Request::new()
+            .target(our())
+            .body(request_body)
+            .send();
+    }
+
+    // Drop a delivered entry, or schedule the next attempt with exponential backoff.
+    // Entries older than OUTBOX_MAX_AGE_SECS are marked failed and reported to the UI.
+    fn record_outbox_attempt(&mut self, entry_id: &str, result: Result<(), String>) {
+        match result {
+            Ok(()) => self.outbox.retain(|e| e.id != entry_id),
+            Err(e) => {
+                let Some(entry) = self.outbox.iter_mut().find(|e| e.id == entry_id) else {
+                    // Cancelled while the attempt was in flight
+                    return;
+                };
+                let now = Utc::now();
+                entry.in_flight = false;
+                entry.attempts += 1;
+                entry.next_attempt_at = (now + outbox_backoff(entry.attempts)).to_rfc3339();
+                println!("Delivery to {} failed (attempt {}): {}", entry.target_node, entry.attempts, e);
+                entry.last_error = Some(e);
+
+                let expired = chrono::DateTime::parse_from_rfc3339(&entry.created_at)
+                    .map(|created| now - created.with_timezone(&Utc) > chrono::Duration::seconds(OUTBOX_MAX_AGE_SECS))
+                    .unwrap_or(false);
+                if expired {
+                    entry.failed = true;
+                    println!("Giving up on delivery to {} after {} attempts", entry.target_node, entry.attempts);
+                    let event = WsEvent::OutboxEntryFailed {
+                        entry_id: entry.id.clone(),
+                        target_node: entry.target_node.clone(),
+                        request_kind: entry.request_kind(),
+                        last_error: entry.last_error.clone(),
+                    };
+                    self.push_where(event, |_| true);
+                }
+            }
+        }
+    }
+}
+
+impl OutboxEntry {
+    // Remote handler being called, e.g. "ReceiveMessage"
+    fn request_kind(&self) -> String {
+        self.request.as_object()
+            .and_then(|o| o.keys().next().cloned())
+            .unwrap_or_default()
+    }
+}
+
+// --- Conversation helpers ---
+impl Conversation {
+    // A direct conversation between two nodes, with no messages yet
+    fn direct(id: &str, node: &str, other: &str, last_updated: &str) -> Conversation {
+        let mut participants = vec![node.to_string(), other.to_string()];
+        participants.sort();
+        Conversation {
+            id: id.to_string(),
+            participants,
+            last_updated: last_updated.to_string(),
+            ..Default::default()
+        }
+    }
+
+    // A group conversation with no messages, roles or membership log yet
+    fn group(id: &str, participants: Vec<String>, group_name: &str, created_by: Option<String>, last_updated: &str) -> Conversation {
+        Conversation {
+            id: id.to_string(),
+            participants,
+            last_updated: last_updated.to_string(),
+            is_group: true,
+            group_name: Some(group_name.to_string()),
+            created_by,
+            ..Default::default()
+        }
+    }
+
+    // Move `reader`'s read pointer forward and mark the covered messages as read by them.
+    // Returns false if the pointer was already at or past the message.
+    fn apply_read_receipt(&mut self, reader: &str, up_to_message_id: &str) -> Result<bool, String> {
+        let up_to = self.messages.iter()
+            .position(|m| m.id == up_to_message_id)
+            .ok_or_else(|| "Message not found".to_string())?;
+
+        let current = self.last_read.get(reader)
+            .and_then(|id| self.messages.iter().position(|m| &m.id == id));
+        if current.is_some_and(|current| current >= up_to) {
+            return Ok(false);
+        }
+
+        for message in self.messages.iter_mut().take(up_to + 1) {
+            if message.sender != reader && !message.read_by.iter().any(|r| r == reader) {
+                message.read_by.push(reader.to_string());
+            }
+        }
+        self.last_read.insert(reader.to_string(), up_to_message_id.to_string());
+        Ok(true)
+    }
+
+    // Role of a node in this group; without an explicit role the creator is the owner
+    fn role_of(&self, node: &str) -> GroupRole {
+        self.roles.get(node).copied().unwrap_or(
+            if self.created_by.as_deref() == Some(node) { GroupRole::Owner } else { GroupRole::Member }
+        )
+    }
+
+    // Whether a node may add or remove members and change group settings
+    fn can_manage(&self, node: &str) -> bool {
+        self.participants.iter().any(|p| p == node) && self.role_of(node) != GroupRole::Member
+    }
+
+    // Highest epoch in the membership log (0 for groups without one)
+    fn epoch(&self) -> u64 {
+        self.membership_log.iter().map(|op| op.epoch).max().unwrap_or(0)
+    }
+
+    // Whether an op is the group's genesis: the epoch 0 create op signed by the creator.
+    // Any other create op is forged and must be ignored.
+    fn is_genesis_op(&self, op: &MembershipOp) -> bool {
+        op.action == MembershipAction::Create
+            && op.epoch == 0
+            && op.prev.is_empty()
+            && self.created_by.as_deref() == Some(op.author.as_str())
+    }
+
+    // Add ops we have not seen yet and recompute membership; returns the new ops.
+    // Create ops are only accepted as the genesis op, and only once.
+    fn merge_membership_ops(&mut self, ops: Vec<MembershipOp>) -> Vec<MembershipOp> {
+        let mut added: Vec<MembershipOp> = Vec::new();
+        for op in ops {
+            let seen = self.membership_log.iter().chain(added.iter()).any(|o| o.op_id == op.op_id);
+            if op.group_id != self.id || seen {
+                continue;
+            }
+            if op.action == MembershipAction::Create {
+                let has_genesis = self.membership_log.iter().chain(added.iter()).any(|o| o.action == MembershipAction::Create);
+                if has_genesis || !self.is_genesis_op(&op) {
+                    println!("Ignored create op for {} by {}", self.id, op.author);
+                    continue;
+                }
+            }
+            added.push(op);
+        }
+        if !added.is_empty() {
+            self.membership_log.extend(added.iter().cloned());
+            self.replay_membership_log();
+        }
+        added
+    }
+
+    // Index of each op in the (sorted) log by hash, and whether each op is well-formed:
+    // the genesis op, or an op whose `prev` are all well-formed ops in the log and whose
+    // epoch is one past theirs. Other ops are ignored until what they build on arrives.
+    fn membership_op_validity(&self) -> (HashMap<String, usize>, Vec<bool>) {
+        let index: HashMap<String, usize> = self.membership_log.iter()
+            .enumerate()
+            .map(|(i, op)| (membership_op_hash(op), i))
+            .collect();
+        let mut valid: Vec<bool> = Vec::with_capacity(self.membership_log.len());
+        for op in &self.membership_log {
+            let is_valid = if self.is_genesis_op(op) {
+                true
+            } else {
+                // Earlier ops have lower epochs, so their validity is already known
+                let prev: Option<Vec<usize>> = op.prev.iter()
+                    .map(|hash| index.get(hash).copied().filter(|&i| i < valid.len() && valid[i]))
+                    .collect();
+                prev.filter(|prev| !prev.is_empty())
+                    .and_then(|prev| prev.iter().map(|&i| self.membership_log[i].epoch).max())
+                    .is_some_and(|max_epoch| op.epoch == max_epoch + 1)
+            };
+            valid.push(is_valid);
+        }
+        (index, valid)
+    }
+
+    // Well-formed ops no other op builds on; a new op lists them as its `prev`
+    fn membership_log_heads(&self) -> Vec<&MembershipOp> {
+        let (_, valid) = self.membership_op_validity();
+        let referenced: HashSet<&str> = self.membership_log.iter()
+            .zip(&valid)
+            .filter(|(_, valid)| **valid)
+            .flat_map(|(op, _)| op.prev.iter().map(String::as_str))
+            .collect();
+        self.membership_log.iter()
+            .zip(&valid)
+            .filter(|(op, valid)| **valid && !referenced.contains(membership_op_hash(op).as_str()))
+            .map(|(op, _)| op)
+            .collect()
+    }
+
+    // Derive participants and roles from the log. Ops whose author lacked the required
+    // role at that point in the order stay in the log but have no effect. So do ops an
+    // author made without having seen its own removal or demotion: an op that precedes
+    // the removal in the order but is not among the ops the remover had seen is either
+    // concurrent with it or backdated, and the removal wins.
+    fn replay_membership_log(&mut self) {
+        self.membership_log.sort_by(|a, b| (a.epoch, &a.op_id).cmp(&(b.epoch, &b.op_id)));
+        // Without the genesis op we cannot know the starting membership; keep what we have
+        if !self.membership_log.iter().any(|op| self.is_genesis_op(op)) {
+            return;
+        }
+
+        let (index, valid) = self.membership_op_validity();
+        let mut ignored: HashSet<usize> = (0..valid.len()).filter(|&i| !valid[i]).collect();
+        let mut replay = self.apply_membership_ops(&ignored);
+        let mut unseen: HashSet<usize> = HashSet::new();
+        for (revoked_at, node) in &replay.revocations {
+            // Ops the author of the removal had seen, following `prev` back to the genesis op
+            let mut seen: HashSet<usize> = HashSet::new();
+            let mut stack = vec![*revoked_at];
+            while let Some(i) = stack.pop() {
+                for hash in &self.membership_log[i].prev {
+                    if let Some(&prev) = index.get(hash) {
+                        if seen.insert(prev) {
+                            stack.push(prev);
+                        }
+                    }
+                }
+            }
+            unseen.extend((0..*revoked_at).filter(|i| {
+                &self.membership_log[*i].author == node && !seen.contains(i) && !ignored.contains(i)
+            }));
+        }
+        if !unseen.is_empty() {
+            ignored.extend(unseen);
+            replay = self.apply_membership_ops(&ignored);
+        }
+
+        self.participants = replay.participants;
+        self.roles = replay.roles;
+        self.disbanded = replay.disbanded;
+        if replay.disbanded {
+            self.left = true;
+            self.pending_requests.clear();
+        }
+        // Requests from nodes that have been added some other way are settled
+        self.pending_requests.retain(|r| !self.participants.contains(&r.node));
+    }
+
+    // Replay the sorted log, skipping the ops at `ignored`
+    fn apply_membership_ops(&self, ignored: &HashSet<usize>) -> MembershipReplay {
+        let mut participants: Vec<String> = Vec::new();
+        let mut roles: HashMap<String, GroupRole> = HashMap::new();
+        let mut revocations: Vec<(usize, String)> = Vec::new();
+        let mut created = false;
+        let mut disbanded = false;
+        for (i, op) in self.membership_log.iter().enumerate() {
+            if ignored.contains(&i) {
+                continue;
+            }
+            let author_role = participants.contains(&op.author)
+                .then(|| roles.get(&op.author).copied().unwrap_or(GroupRole::Member));
+            let author_is_admin = matches!(author_role, Some(GroupRole::Owner | GroupRole::Admin));
+            match op.action {
+                MembershipAction::Create if !created && self.is_genesis_op(op) => {
+                    created = true;
+                    for member in &op.members {
+                        if !participants.contains(&member.node) {
+                            participants.push(member.node.clone());
+                        }
+                        roles.insert(member.node.clone(), member.role);
+                    }
+                    if !participants.contains(&op.author) {
+                        participants.push(op.author.clone());
+                    }
+                },
+                MembershipAction::Add if author_is_admin => {
+                    for member in &op.members {
+                        if !participants.contains(&member.node) {
+                            participants.push(member.node.clone());
+                            roles.insert(member.node.clone(), GroupRole::Member);
+                        }
+                    }
+                },
+                MembershipAction::Remove if author_is_admin => {
+                    for member in &op.members {
+                        // The owner cannot be removed, and only the owner can remove admins
+                        let allowed = match roles.get(&member.node) {
+                            Some(GroupRole::Owner) => false,
+                            Some(GroupRole::Admin) => author_role == Some(GroupRole::Owner),
+                            _ => true,
+                        };
+                        if allowed && participants.contains(&member.node) {
+                            participants.retain(|p| p != &member.node);
+                            roles.remove(&member.node);
+                            revocations.push((i, member.node.clone()));
+                        }
+                    }
+                },
+                MembershipAction::Leave if author_role.is_some() => {
+                    participants.retain(|p| p != &op.author);
+                    roles.remove(&op.author);
+                    revocations.push((i, op.author.clone()));
+                },
+                MembershipAction::SetRole if author_role == Some(GroupRole::Owner) => {
+                    for member in &op.members {
+                        if participants.contains(&member.node) && member.node != op.author && member.role != GroupRole::Owner {
+                            if member.role == GroupRole::Member && roles.get(&member.node) == Some(&GroupRole::Admin) {
+                                revocations.push((i, member.node.clone()));
+                            }
+                            roles.insert(member.node.clone(), member.role);
+                        }
+                    }
+                },
+                MembershipAction::TransferOwnership if author_role == Some(GroupRole::Owner) => {
+                    if let Some(new_owner) = op.members.first().filter(|m| m.node != op.author && participants.contains(&m.node)) {
+                        roles.insert(new_owner.node.clone(), GroupRole::Owner);
+                        roles.insert(op.author.clone(), GroupRole::Admin);
+                        revocations.push((i, op.author.clone()));
+                    }
+                },
+                MembershipAction::Disband if author_role == Some(GroupRole::Owner) => {
+                    disbanded = true;
+                    break;
+                },
+                _ => {},
+            }
+        }
+        MembershipReplay { participants, roles, disbanded, revocations }
+    }
+
+    // Append a system event to the timeline and return it
+    fn push_system_event(&mut self, actor: &str, content: String) -> ChatMessage {
+        let timestamp = Utc::now().to_rfc3339();
+        let message = ChatMessage {
+            id: Uuid::new_v4().to_string(),
+            conversation_id: self.id.clone(),
+            sender: actor.to_string(),
+            recipient: None,
+            recipients: None,
+            content,
+            timestamp: timestamp.clone(),
+            delivered: true,
+            delivered_to: Vec::new(),
+            read_by: Vec::new(),
+            file_info: None,
+            reply_to: None,
+            kind: MessageKind::System,
+        };
+        self.messages.push(message.clone());
+        self.last_updated = timestamp;
+        message
+    }
+
+    // Current name, description and avatar, as sent to other members
+    fn group_info(&self) -> GroupInfoUpdate {
+        GroupInfoUpdate {
+            group_id: self.id.clone(),
+            group_name: self.group_name.clone().unwrap_or_default(),
+            description: self.group_description.clone().unwrap_or_default(),
+            avatar: self.group_avatar.clone(),
+            announcement_only: self.announcement_only,
+            updated_at: self.group_info_updated_at.clone().unwrap_or_default(),
+        }
+    }
+
+    // Apply group metadata and posting mode unless we already have something newer.
+    // Returns a timeline description of each change.
+    fn apply_group_info(&mut self, actor: &str, info: &GroupInfoUpdate) -> Vec<String> {
+        if self.group_info_updated_at.as_deref().is_some_and(|t| t > info.updated_at.as_str()) {
+            return Vec::new();
+        }
+        let description = Some(info.description.clone()).filter(|d| !d.trim().is_empty());
+
+        let mut changes = Vec::new();
+        if self.group_name.as_deref() != Some(info.group_name.as_str()) {
+            changes.push(format!("{} renamed the group to \"{}\"", actor, info.group_name));
+        }
+        if self.group_description != description {
+            changes.push(if description.is_some() {
+                format!("{} changed the group description", actor)
+            } else {
+                format!("{} removed the group description", actor)
+            });
+        }
+        if self.group_avatar.as_ref().map(|a| &a.file_id) != info.avatar.as_ref().map(|a| &a.file_id) {
+            changes.push(if info.avatar.is_some() {
+                format!("{} changed the group avatar", actor)
+            } else {
+                format!("{} removed the group avatar", actor)
+            });
+        }
+
+        if self.announcement_only != info.announcement_only {
+            changes.push(if info.announcement_only {
+                format!("{} made the group announcement-only", actor)
+            } else {
+                format!("{} allowed everyone to post again", actor)
+            });
+        }
+
+        self.group_name = Some(info.group_name.clone());
+        self.group_description = description;
+        self.group_avatar = info.avatar.clone();
+        self.announcement_only = info.announcement_only;
+        self.group_info_updated_at = Some(info.updated_at.clone());
+        changes
+    }
+
+    // Every participant with their role
+    fn member_roles(&self) -> Vec<GroupMember> {
+        self.participants.iter()
+            .map(|p| GroupMember { node: p.clone(), role: self.role_of(p) })
+            .collect()
+    }
+
+    // Number of messages from others that come after `reader`'s read pointer (system events excluded)
+    fn unread_count(&self, reader: &str) -> u64 {
+        let start = self.last_read.get(reader)
+            .and_then(|id| self.messages.iter().position(|m| &m.id == id))
+            .map(|pos| pos + 1)
+            .unwrap_or(0);
+        self.messages[start..].iter()
+            .filter(|m| m.sender != reader && m.kind == MessageKind::User)
+            .count() as u64
+    }
+}
+
+// --- Membership helpers ---
+impl SamchatState {
+    // Sign a membership op at the next epoch and apply it locally. Groups created before
+    // the log existed first get a signed snapshot of their current membership as epoch 0,
+    // which only the creator may write. Returns the new ops along with the membership
+    // from before the change.
+    fn commit_membership_op(&mut self, group_id: &str, action: MembershipAction, members: Vec<GroupMember>) -> Result<(Vec<MembershipOp>, Vec<GroupMember>), String> {
+        let me = self.my_node_id.clone().ok_or_else(|| "Node ID not initialized".to_string())?;
+        let conversation = self.conversations.get_mut(group_id)
+            .ok_or_else(|| "Group not found".to_string())?;
+        let before = conversation.member_roles();
+
+        let mut ops = Vec::new();
+        if conversation.membership_log.is_empty() {
+            if conversation.created_by.as_deref() != Some(me.as_str()) {
+                return Err("Only the group creator can change membership until the group has a membership log".to_string());
+            }
+            let genesis = new_membership_op(group_id, 0, &me, MembershipAction::Create, before.clone(), Vec::new())?;
+            conversation.merge_membership_ops(vec![genesis.clone()]);
+            ops.push(genesis);
+        }
+        let heads = conversation.membership_log_heads();
+        let epoch = heads.iter().map(|op| op.epoch).max().unwrap_or(0) + 1;
+        let prev = heads.into_iter().map(membership_op_hash).collect();
+        let op = new_membership_op(group_id, epoch, &me, action, members, prev)?;
+        conversation.merge_membership_ops(vec![op.clone()]);
+        ops.push(op);
+        conversation.last_updated = Utc::now().to_rfc3339();
+        Ok((ops, before))
+    }
+
+    // Add a member on our authority, send them the group and tell everyone else.
+    // The caller has checked that we may manage the group.
+    fn admit_group_member(&mut self, group_id: &str, new_member: &str) -> Result<(), String> {
+        let me = self.my_node_id.clone().ok_or_else(|| "Node ID not initialized".to_string())?;
+        let added = GroupMember { node: new_member.to_string(), role: GroupRole::Member };
+        let (ops, before) = self.commit_membership_op(group_id, MembershipAction::Add, vec![added])?;
+        self.send_group_join(group_id, new_member)?;
+
+        // Tell everyone else so they fan out messages to the new member too
+        let existing_members: Vec<String> = before.iter()
+            .map(|m| m.node.clone())
+            .filter(|p| p != &me)
+            .collect();
+        self.send_membership_ops(&ops, &existing_members);
+        self.announce_membership_changes(group_id, &me, &before, &ops);
+        Ok(())
+    }
+
+    // Check that a node has a pending join request we may act on
+    fn check_join_request(&self, group_id: &str, node: &str) -> Result<(), String> {
+        let me = self.my_node_id.clone().ok_or_else(|| "Node ID not initialized".to_string())?;
+        let conversation = self.conversations.get(group_id)
+            .ok_or_else(|| "Group not found".to_string())?;
+        if !conversation.is_group || conversation.left || !conversation.can_manage(&me) {
+            return Err("Only group admins can handle join requests".to_string());
+        }
+        if !conversation.pending_requests.iter().any(|r| r.node == node) {
+            return Err("No pending join request from this node".to_string());
+        }
+        Ok(())
+    }
+
+    // Drop a node's pending join request once it has been handled
+    fn remove_join_request(&mut self, group_id: &str, node: &str) {
+        if let Some(conversation) = self.conversations.get_mut(group_id) {
+            conversation.pending_requests.retain(|r| r.node != node);
+        }
+    }
+
+    // Send a member everything they need to set up (or refresh) their copy of the group
+    fn send_group_join(&mut self, group_id: &str, member: &str) -> Result<(), String> {
+        let conversation = self.conversations.get(group_id)
+            .ok_or_else(|| "Group not found".to_string())?;
+        let notification = GroupJoinNotification {
+            group_id: group_id.to_string(),
+            group_name: conversation.group_name.clone().unwrap_or_default(),
+            participants: conversation.participants.clone(),
+            created_by: conversation.created_by.clone().unwrap_or_default(),
+            roles: conversation.member_roles(),
+            membership_log: conversation.membership_log.clone(),
+            info: Some(conversation.group_info()),
+        };
+        self.enqueue_remote(member, json!({
+            "HandleGroupJoin": notification
+        }));
+        Ok(())
+    }
+
+    // Send membership ops to other members as a single batch
+    fn send_membership_ops(&mut self, ops: &[MembershipOp], recipients: &[String]) {
+        for recipient in recipients {
+            self.enqueue_remote(recipient, json!({
+                "HandleMembershipOps": ops
+            }));
+        }
+    }
+
+    // Push WebSocket events and record timeline events for everything that changed since
+    // the `before` snapshot. Changes not explained by `new_ops` are attributed to `actor`.
+    fn announce_membership_changes(&mut self, group_id: &str, actor: &str, before: &[GroupMember], new_ops: &[MembershipOp]) {
+        let Some(conversation) = self.conversations.get(group_id) else {
+            return;
+        };
+        let after = conversation.member_roles();
+        let conversation_id = group_id.to_string();
+        // Author of the op that affected `node`, if any
+        let author_of = |action: MembershipAction, node: &str| -> String {
+            new_ops.iter()
+                .find(|op| op.action == action && op.members.iter().any(|m| m.node == node))
+                .map(|op| op.author.clone())
+                .unwrap_or_else(|| actor.to_string())
+        };
+
+        let mut changes: Vec<(WsEvent, String, Option<String>)> = Vec::new(); // (event, actor, timeline text)
+        for old in before {
+            let member = old.node.clone();
+            match after.iter().find(|m| m.node == old.node) {
+                None => {
+                    let left = new_ops.iter().any(|op| op.action == MembershipAction::Leave && op.author == old.node);
+                    if left {
+                        let text = format!("{} left the group", member);
+                        changes.push((WsEvent::GroupMemberLeft { conversation_id: conversation_id.clone(), member: member.clone() }, member, Some(text)));
+                    } else {
+                        let remover = author_of(MembershipAction::Remove, &member);
+                        let text = format!("{} removed {}", remover, member);
+                        changes.push((WsEvent::GroupMemberRemoved { conversation_id: conversation_id.clone(), member }, remover, Some(text)));
+                    }
+                },
+                Some(new) if new.role != old.role => {
+                    let transfer = new_ops.iter().find(|op| op.action == MembershipAction::TransferOwnership);
+                    let (changer, text) = match transfer {
+                        Some(op) if new.role == GroupRole::Owner => {
+                            (op.author.clone(), Some(format!("{} transferred ownership to {}", op.author, member)))
+                        },
+                        // The previous owner's demotion is part of the transfer above
+                        Some(op) if op.author == member => (op.author.clone(), None),
+                        _ => {
+                            let changer = author_of(MembershipAction::SetRole, &member);
+                            let text = format!("{} made {} {}", changer, member, role_description(new.role));
+                            (changer, Some(text))
+                        },
+                    };
+                    changes.push((WsEvent::RoleChanged { conversation_id: conversation_id.clone(), member, role: new.role }, changer, text));
+                },
+                Some(_) => {},
+            }
+        }
+        for new in &after {
+            if !before.iter().any(|m| m.node == new.node) {
+                let adder = author_of(MembershipAction::Add, &new.node);
+                let text = format!("{} added {}", adder, new.node);
+                changes.push((WsEvent::GroupMemberAdded { conversation_id: conversation_id.clone(), member: new.node.clone() }, adder, Some(text)));
+            }
+        }
+        if conversation.disbanded {
+            if let Some(op) = new_ops.iter().find(|op| op.action == MembershipAction::Disband) {
+                let text = format!("{} disbanded the group", op.author);
+                changes.push((WsEvent::GroupDisbanded { conversation_id: conversation_id.clone(), disbanded_by: op.author.clone() }, op.author.clone(), Some(text)));
+            }
+        }
+
+        for (event, change_actor, text) in changes {
+            self.push_where(event, |s| s.watches(group_id));
+            if let Some(text) = text {
+                self.record_system_event(group_id, &change_actor, text);
+            }
+        }
+    }
+}
+
+// --- Group info helpers ---
+impl SamchatState {
+    // Apply our own group info update and send it to the other members
+    fn broadcast_group_info_update(&mut self, me: &str, update: GroupInfoUpdate, others: &[String]) {
+        self.apply_group_info_update(me, update.clone());
+        for participant in others {
+            self.enqueue_remote(participant, json!({
+                "HandleGroupInfoUpdate": update
+            }));
+        }
+    }
+
+    // Apply a group info update from `actor`, recording each change in the timeline
+    fn apply_group_info_update(&mut self, actor: &str, update: GroupInfoUpdate) {
+        let Some(conversation) = self.conversations.get_mut(&update.group_id) else {
+            return;
+        };
+        let changes = conversation.apply_group_info(actor, &update);
+        if changes.is_empty() {
+            return;
+        }
+
+        // Members may fetch the avatar from whichever node serves it
+        if let Some(avatar) = &update.avatar {
+            self.record_shared_file(&avatar.file_id, &update.group_id);
+        }
+        let event = WsEvent::GroupInfoUpdated {
+            conversation_id: update.group_id.clone(),
+            group_name: update.group_name.clone(),
+            description: Some(update.description.clone()).filter(|d| !d.trim().is_empty()),
+            avatar: update.avatar.clone(),
+            announcement_only: update.announcement_only,
+        };
+        self.push_where(event, |s| s.watches(&update.group_id));
+        for content in changes {
+            self.record_system_event(&update.group_id, actor, content);
+        }
+    }
+}
+
+// --- Timeline helpers ---
+impl SamchatState {
+    // Append a system event to a conversation and push it to WebSocket clients
+    fn record_system_event(&mut self, conversation_id: &str, actor: &str, content: String) {
+        let Some(conversation) = self.conversations.get_mut(conversation_id) else {
+            return;
+        };
+        let message = conversation.push_system_event(actor, content);
+        self.push_where(WsEvent::NewMessage { message }, |s| s.watches(conversation_id));
+    }
+}
+
+// --- File helpers ---
+impl SamchatState {
+    // Where a file's content is stored. Files stored before content hashing keep their
+    // per-ID path.
+    fn file_path(&self, file_id: &str) -> String {
+        match self.file_hashes.get(file_id) {
+            Some(hash) => format!("{}/{}", FILES_DIR, hash),
+            None => format!("{}/{}", FILES_DIR, file_id),
+        }
+    }
+
+    // Store verified content under its hash, skipping the write if we already hold it
+    fn store_file_content(&mut self, file_id: &str, content_hash: &str, data: Vec<u8>) -> Result<(), String> {
+        if !self.file_hashes.values().any(|hash| hash == content_hash) {
+            vfs_request(FILES_DIR, json!("CreateDirAll"), None)?;
+            vfs_request(&format!("{}/{}", FILES_DIR, content_hash), json!("Write"), Some(data))?;
+        }
+        self.file_hashes.insert(file_id.to_string(), content_hash.to_string());
+        Ok(())
+    }
+
+    // Like store_file_content, for verified content already in VFS at `path`: the file is
+    // moved under its hash, or removed if we already hold that content
+    fn store_file_from_path(&mut self, file_id: &str, content_hash: &str, path: &str) -> Result<(), String> {
+        if self.file_hashes.values().any(|hash| hash == content_hash) {
+            if let Err(e) = vfs_request(path, json!("RemoveFile"), None) {
+                println!("Failed to remove {}: {}", path, e);
+            }
+        } else {
+            vfs_request(FILES_DIR, json!("CreateDirAll"), None)?;
+            vfs_request(path, json!({ "Rename": { "new_path": format!("{}/{}", FILES_DIR, content_hash) } }), None)?;
+        }
+        self.file_hashes.insert(file_id.to_string(), content_hash.to_string());
+        Ok(())
+    }
+
+    // Content hash announced for a file in the conversations it was shared in
+    fn announced_hash(&self, file_id: &str) -> Option<String> {
+        let conversation_ids = self.file_conversations.get(file_id)?;
+        conversation_ids.iter()
+            .filter_map(|id| self.conversations.get(id))
+            .flat_map(|c| c.messages.iter().filter_map(|m| m.file_info.as_ref()).chain(c.group_avatar.as_ref()))
+            .find(|f| f.file_id == file_id)
+            .and_then(|f| f.content_hash.clone())
+    }
+
+    // Whether a node takes part in a conversation the file was shared in
+    fn may_fetch_file(&self, file_id: &str, node: &str) -> bool {
+        validate_file_id(file_id).is_ok() && self.file_conversations.get(file_id)
+            .is_some_and(|conversation_ids| conversation_ids.iter().any(|id| {
+                self.conversations.get(id).is_some_and(|c| c.participants.iter().any(|p| p == node))
+            }))
+    }
+
+    // Whether a peer may announce a file in a conversation. Announcing grants the other
+    // participants access, so a file we already hold, or that was shared in another
+    // conversation, is refused: naming its ID must not expose it.
+    fn accepts_remote_file(&self, file_id: &str, conversation_id: &str) -> bool {
+        if validate_file_id(file_id).is_err() {
+            return false;
+        }
+        match self.file_conversations.get(file_id) {
+            Some(conversation_ids) => conversation_ids.iter().all(|id| id == conversation_id),
+            None => !self.file_hashes.contains_key(file_id) && vfs_len(&self.file_path(file_id)).is_err(),
+        }
+    }
+
+    // Remember that a file was shared in a conversation, granting its participants access
+    fn record_shared_file(&mut self, file_id: &str, conversation_id: &str) {
+        let conversation_ids = self.file_conversations.entry(file_id.to_string()).or_default();
+        if !conversation_ids.iter().any(|id| id == conversation_id) {
+            conversation_ids.push(conversation_id.to_string());
+        }
+    }
+}
+
+// --- Download helpers ---
+impl SamchatState {
+    // Request chunks until the transfer has reached `total_size`. Each chunk is a separate
+    // request to receive_file_chunk, which can arrive after the response to its request;
+    // we wait for it, and ask for it again if it does not turn up.
+    async fn pull_file_chunks(&mut self, transfer_id: &str, file_id: &str, target_address: &Address, total_size: u64) -> Result<(), String> {
+        let mut attempts = 0;
+        loop {
+            let offset = self.transfer_offset(transfer_id)?;
+            if offset >= total_size {
+                return Ok(());
+            }
+            let request = json!({
+                "RequestFileChunk": FileTransferRequest {
+                    transfer_id: transfer_id.to_string(),
+                    file_id: file_id.to_string(),
+                    offset,
+                }
+            });
+            match send::<Result<FileChunk, String>>(&request, target_address, REMOTE_TIMEOUT_SECS).await {
+                SendResult::Success(Ok(_)) => {},
+                SendResult::Success(Err(e)) => return Err(format!("Remote error: {}", e)),
+                SendResult::Timeout => return Err("File transfer timed out".to_string()),
+                SendResult::Offline => return Err("Sender node is offline".to_string()),
+                SendResult::DeserializationError(e) => return Err(format!("Unexpected response from sender: {}", e)),
+            }
+
+            let mut steps = 0;
+            while self.transfer_offset(transfer_id)? == offset && steps < CHUNK_WAIT_STEPS {
+                sleep_ms(CHUNK_WAIT_MS).await;
+                steps += 1;
+            }
+            if self.transfer_offset(transfer_id)? == offset {
+                attempts += 1;
+                if attempts >= CHUNK_REQUEST_ATTEMPTS {
+                    return Err(format!("Chunk at offset {} was not received", offset));
+                }
+                println!("Chunk at offset {} of {} has not arrived, requesting it again", offset, file_id);
+            } else {
+                attempts = 0;
+            }
+        }
+    }
+
+    // Offset the next chunk of a running download must start at
+    fn transfer_offset(&self, transfer_id: &str) -> Result<u64, String> {
+        self.incoming_transfers.get(transfer_id)
+            .map(|t| t.next_offset)
+            .ok_or_else(|| "Transfer state lost".to_string())
+    }
+}
+
+impl PartialDownload {
+    // Length of the unbroken run of bytes from the start of the file
+    fn contiguous_bytes(&self) -> u64 {
+        self.received_ranges.first()
+            .filter(|range| range.start == 0)
+            .map(|range| range.end)
+            .unwrap_or(0)
+    }
+
+    // Record a received range, merging it with any it touches
+    fn add_range(&mut self, start: u64, end: u64) {
+        let mut merged = ByteRange { start, end };
+        self.received_ranges.retain(|range| {
+            let touches = range.start <= merged.end && merged.start <= range.end;
+            if touches {
+                merged.start = merged.start.min(range.start);
+                merged.end = merged.end.max(range.end);
+            }
+            !touches
+        });
+        let position = self.received_ranges.iter()
+            .position(|range| range.start > merged.start)
+            .unwrap_or(self.received_ranges.len());
+        self.received_ranges.insert(position, merged);
+    }
+}
+
+// --- WebSocket helpers ---
+impl SamchatState {
+    // Push an event to every open channel whose subscription matches `filter`
+    fn push_where(&self, event: WsEvent, filter: impl Fn(&WsSubscription) -> bool) {
+        let bytes = match serde_json::to_vec(&event) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                println!("Failed to serialize WebSocket event: {}", e);
+                return;
+            }
+        };
+        for (channel_id, subscription) in &self.ws_subscriptions {
+            if filter(subscription) {
+                send_ws_push(*channel_id, WsMessageType::Text, LazyLoadBlob::new(Some("application/json"), bytes.clone()));
+            }
+        }
+    }
+}
+
+impl WsSubscription {
+    // Whether this channel receives events for a conversation
+    fn watches(&self, conversation_id: &str) -> bool {
+        self.all || self.conversation_ids.contains(conversation_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(created_by: &str) -> Conversation {
+        Conversation::group("group", Vec::new(), "Test", Some(created_by.to_string()), "")
+    }
+
+    // Replay does not check signatures (that happens on receipt), so tests can leave them empty
+    fn op(epoch: u64, op_id: &str, author: &str, action: MembershipAction, members: &[(&str, GroupRole)], prev: &[&MembershipOp]) -> MembershipOp {
+        MembershipOp {
+            group_id: "group".to_string(),
+            epoch,
+            op_id: op_id.to_string(),
+            author: author.to_string(),
+            action,
+            members: members.iter()
+                .map(|(node, role)| GroupMember { node: node.to_string(), role: *role })
+                .collect(),
+            prev: prev.iter().map(|op| membership_op_hash(op)).collect(),
+            timestamp: String::new(),
+            signature: Vec::new(),
+        }
+    }
+
+    fn genesis() -> MembershipOp {
+        op(0, "b-genesis", "alice.os", MembershipAction::Create, &[
+            ("alice.os", GroupRole::Owner),
+            ("bob.os", GroupRole::Member),
+        ], &[])
+    }
+
+    #[test]
+    fn forged_create_does_not_take_over_group() {
+        let forged = op(0, "0000", "mallory.os", MembershipAction::Create, &[("mallory.os", GroupRole::Owner)], &[]);
+
+        let mut merged = group("alice.os");
+        merged.merge_membership_ops(vec![forged.clone(), genesis()]);
+        assert!(!merged.membership_log.contains(&forged));
+        assert_eq!(merged.participants, vec!["alice.os", "bob.os"]);
+        assert_eq!(merged.role_of("alice.os"), GroupRole::Owner);
+
+        // A forged create already in the log is ignored by replay as well
+        let mut replayed = group("alice.os");
+        replayed.membership_log = vec![genesis(), forged];
+        replayed.replay_membership_log();
+        assert_eq!(replayed.participants, vec!["alice.os", "bob.os"]);
+        assert!(!replayed.can_manage("mallory.os"));
+
+        // Without the creator's genesis op, a forged create gives no membership at all
+        let mut orphan = group("alice.os");
+        orphan.merge_membership_ops(vec![op(0, "0000", "mallory.os", MembershipAction::Create, &[("mallory.os", GroupRole::Owner)], &[])]);
+        assert!(orphan.membership_log.is_empty());
+        assert!(orphan.participants.is_empty());
+    }
+
+    #[test]
+    fn remove_by_non_admin_has_no_effect() {
+        let genesis = genesis();
+        let add = op(1, "a", "alice.os", MembershipAction::Add, &[("carol.os", GroupRole::Member)], &[&genesis]);
+        let remove_carol = op(2, "b", "bob.os", MembershipAction::Remove, &[("carol.os", GroupRole::Member)], &[&add]);
+        let remove_alice = op(3, "c", "bob.os", MembershipAction::Remove, &[("alice.os", GroupRole::Member)], &[&remove_carol]);
+
+        let mut conversation = group("alice.os");
+        conversation.merge_membership_ops(vec![genesis, add, remove_carol, remove_alice]);
+        assert_eq!(conversation.participants, vec!["alice.os", "bob.os", "carol.os"]);
+        assert_eq!(conversation.role_of("alice.os"), GroupRole::Owner);
+    }
+
+    #[test]
+    fn backdated_ops_after_removal_or_demotion_have_no_effect() {
+        let genesis = genesis();
+        let promote = op(1, "a", "alice.os", MembershipAction::SetRole, &[("bob.os", GroupRole::Admin)], &[&genesis]);
+        let add_carol = op(2, "b", "bob.os", MembershipAction::Add, &[("carol.os", GroupRole::Member)], &[&promote]);
+        let remove_bob = op(3, "c", "alice.os", MembershipAction::Remove, &[("bob.os", GroupRole::Member)], &[&add_carol]);
+        // Made by bob after his removal, but claiming to build on an op from before it so
+        // that it sorts ahead of the removal
+        let backdated = op(2, "a-late", "bob.os", MembershipAction::Add, &[("dave.os", GroupRole::Member)], &[&promote]);
+
+        let mut conversation = group("alice.os");
+        conversation.merge_membership_ops(vec![genesis.clone(), promote.clone(), add_carol.clone(), remove_bob]);
+        conversation.merge_membership_ops(vec![backdated]);
+        // Bob's add was seen by alice before she removed him, so it still counts
+        assert_eq!(conversation.participants, vec!["alice.os", "carol.os"]);
+
+        // The same holds for a demotion
+        let demote_bob = op(3, "c", "alice.os", MembershipAction::SetRole, &[("bob.os", GroupRole::Member)], &[&add_carol]);
+        let backdated = op(3, "a-late", "bob.os", MembershipAction::Remove, &[("carol.os", GroupRole::Member)], &[&add_carol]);
+        let mut conversation = group("alice.os");
+        conversation.merge_membership_ops(vec![genesis, promote, add_carol, demote_bob, backdated]);
+        assert_eq!(conversation.participants, vec!["alice.os", "bob.os", "carol.os"]);
+        assert!(!conversation.can_manage("bob.os"));
+    }
+
+    #[test]
+    fn concurrent_ops_at_same_epoch_converge() {
+        // Both ops were made at epoch 1 without seeing each other; op_id decides the order
+        let promote = op(1, "a-promote", "alice.os", MembershipAction::SetRole, &[("bob.os", GroupRole::Admin)], &[&genesis()]);
+        let add = op(1, "b-add", "bob.os", MembershipAction::Add, &[("carol.os", GroupRole::Member)], &[&genesis()]);
+
+        let mut first = group("alice.os");
+        first.merge_membership_ops(vec![genesis()]);
+        first.merge_membership_ops(vec![promote.clone()]);
+        first.merge_membership_ops(vec![add.clone()]);
+
+        let mut second = group("alice.os");
+        second.merge_membership_ops(vec![genesis()]);
+        second.merge_membership_ops(vec![add]);
+        second.merge_membership_ops(vec![promote]);
+
+        assert_eq!(first.participants, second.participants);
+        assert_eq!(first.member_roles(), second.member_roles());
+        // Bob was promoted before his add in (epoch, op_id) order, so the add counts
+        assert!(first.participants.contains(&"carol.os".to_string()));
+        assert_eq!(first.role_of("bob.os"), GroupRole::Admin);
+    }
 }
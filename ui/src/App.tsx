@@ -950,7 +950,11 @@ function App() {
               
               <div className="message-list" ref={messageListRef}>
                 {currentConversationMessages.length > 0 ? (
-                  currentConversationMessages.map(message => (
+                  currentConversationMessages.map(message => message.kind === 'System' ? (
+                    <div key={message.id} className="system-message">
+                      {message.content}
+                    </div>
+                  ) : (
                     <div 
                       key={message.id}
                       className={`message-item ${message.sender === myNodeId ? 'sent' : 'received'}`}
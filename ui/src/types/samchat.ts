@@ -5,6 +5,7 @@ export interface FileInfo {
   mime_type: string;
   file_id: string;
   sender_node: string;
+  content_hash?: string; // Hex SHA-256 of the content
 }
 
 // Reply info structure
@@ -23,9 +24,24 @@ export interface ChatMessage {
   recipients?: string[]; // Recipients for group messages
   content: string;
   timestamp: string; // ISO string representation of DateTime<Utc>
-  delivered: boolean;
+  delivered: boolean; // True once every recipient has acknowledged delivery
+  delivered_to: string[]; // Recipients that acknowledged delivery
+  read_by: string[]; // Participants that have read this message
   file_info?: FileInfo; // Optional file attachment
   reply_to?: MessageReplyInfo; // Optional reply reference
+  kind: MessageKind; // User message or system timeline event
+}
+
+// System messages are timeline events generated locally (e.g. group changes)
+export type MessageKind = "User" | "System";
+
+// Role of a group member
+export type GroupRole = "Owner" | "Admin" | "Member";
+
+// Group participant with their role
+export interface GroupMember {
+  node: string;
+  role: GroupRole;
 }
 
 // Conversation structure
@@ -37,6 +53,7 @@ export interface Conversation {
   is_group: boolean;
   group_name?: string;
   created_by?: string;
+  left: boolean; // We left this group; history is read-only
 }
 
 // Conversation summary for list display
@@ -46,6 +63,18 @@ export interface ConversationSummary {
   last_updated: string; // ISO string representation of DateTime<Utc>
   is_group: boolean;
   group_name?: string;
+  group_description?: string;
+  group_avatar?: FileInfo; // Download like any shared file
+  announcement_only: boolean; // Only admins may post
+  left: boolean; // We left this group; history is read-only
+  disbanded: boolean; // The group was disbanded by its owner (also read-only)
+  roles: GroupMember[]; // Role of every group participant (empty for direct messages)
+  pending_requests: JoinRequest[]; // Join requests awaiting our approval
+  unread_count: number; // Messages from others after our read pointer
+  last_message_sender?: string;
+  last_message_preview?: string; // Truncated content (or file name for bare attachments)
+  last_message_timestamp?: string;
+  last_message_has_attachment: boolean;
 }
 
 // Define the type for the state managed by the Zustand store
@@ -137,3 +166,197 @@ export interface SendMessageWithReplyRequest {
 // Response type for the send_message_with_reply endpoint
 export type SendMessageWithReplyResponse = RustResponse<boolean>;
 
+
+// Pending remote request in the outbox
+export interface OutboxStatus {
+  id: string;
+  target_node: string;
+  request_kind: string; // Remote handler being called, e.g. "ReceiveMessage"
+  attempts: number;
+  created_at: string;
+  next_attempt_at: string; // ISO string of the next retry
+  last_error?: string;
+  failed: boolean; // Delivery was given up; cancel the entry to remove it
+}
+
+// Request body for the get_outbox_status endpoint
+export interface GetOutboxStatusRequest {
+  GetOutboxStatus: string; // Empty string as per the Rust backend
+}
+
+// Response type for the get_outbox_status endpoint
+export type GetOutboxStatusResponse = RustResponse<OutboxStatus[]>;
+
+// Request body for the cancel_outbox_entry endpoint
+export interface CancelOutboxEntryRequest {
+  CancelOutboxEntry: string; // entry_id
+}
+
+// Response type for the cancel_outbox_entry endpoint
+export type CancelOutboxEntryResponse = RustResponse<boolean>;
+
+// Request body for the mark_read endpoint
+export interface MarkReadRequest {
+  MarkRead: [string, string]; // conversation_id, up_to_message_id
+}
+
+// Response type for the mark_read endpoint
+export type MarkReadResponse = RustResponse<boolean>;
+
+// Direction for paginated message fetches
+export type PageDirection = "Older" | "Newer";
+
+// One page of messages
+export interface MessagePage {
+  messages: ChatMessage[]; // Chronological order, regardless of direction
+  next_cursor?: string; // Continue in the requested direction
+  prev_cursor?: string; // Go back the opposite way
+}
+
+// Request body for the get_messages_page endpoint
+export interface GetMessagesPageRequest {
+  GetMessagesPage: [string, string | null, PageDirection, number]; // conversation_id, cursor (message ID or timestamp), direction, limit
+}
+
+// Response type for the get_messages_page endpoint
+export type GetMessagesPageResponse = RustResponse<MessagePage>;
+
+// --- WebSocket Types ---
+
+// Sent on /ws to choose which conversations to receive events for
+export type WsClientMessage =
+  | { Subscribe: { conversation_ids: string[] } }
+  | { Unsubscribe: { conversation_ids: string[] } }
+  | "SubscribeAll"
+  | "UnsubscribeAll";
+
+// Pushed by the backend on /ws
+export type WsEvent =
+  | { NewMessage: { message: ChatMessage } }
+  | { DeliveryUpdate: { conversation_id: string; message_id: string; delivered: boolean; delivered_to: string[] } }
+  | { ReadUpdate: { conversation_id: string; reader: string; up_to_message_id: string } }
+  | { GroupJoined: { conversation_id: string; group_name: string; participants: string[] } }
+  | { GroupInfoUpdated: { conversation_id: string; group_name: string; description?: string; avatar?: FileInfo; announcement_only: boolean } }
+  | { JoinRequested: { conversation_id: string; request: JoinRequest } }
+  | { JoinRequestResolved: { conversation_id: string; node: string; approved: boolean } }
+  | { GroupMemberAdded: { conversation_id: string; member: string } }
+  | { GroupMemberRemoved: { conversation_id: string; member: string } }
+  | { GroupMemberLeft: { conversation_id: string; member: string } }
+  | { RoleChanged: { conversation_id: string; member: string; role: GroupRole } }
+  | { FileTransferProgress: { file_id: string; received_bytes: number; total_size: number } }
+  | { GroupDisbanded: { conversation_id: string; disbanded_by: string } }
+  | { OutboxEntryFailed: { entry_id: string; target_node: string; request_kind: string; last_error?: string } };
+
+// Request body for the remove_group_member endpoint
+export interface RemoveGroupMemberRequest {
+  RemoveGroupMember: [string, string]; // group_id, member
+}
+
+// Response type for the remove_group_member endpoint
+export type RemoveGroupMemberResponse = RustResponse<boolean>;
+
+// Request body for the update_group_info endpoint
+export interface UpdateGroupInfoRequest {
+  UpdateGroupInfo: [string, string, string, FileInfo | null]; // group_id, group_name, description, avatar
+}
+
+// Response type for the update_group_info endpoint
+export type UpdateGroupInfoResponse = RustResponse<boolean>;
+
+// Request body for the set_announcement_only endpoint
+export interface SetAnnouncementOnlyRequest {
+  SetAnnouncementOnly: [string, boolean]; // group_id, enabled
+}
+
+// Response type for the set_announcement_only endpoint
+export type SetAnnouncementOnlyResponse = RustResponse<boolean>;
+
+// Group invite issued by an admin's node
+export interface GroupInvite {
+  token: string;
+  group_id: string;
+  code: string; // What the invitee enters: "<issuing node>/<token>"
+  created_at: string;
+  expires_at?: string; // Never expires when absent
+  max_uses?: number; // Unlimited when absent
+  uses: number;
+}
+
+// Request body for the create_group_invite endpoint
+export interface CreateGroupInviteRequest {
+  CreateGroupInvite: [string, number | null, number | null]; // group_id, expires_in_secs, max_uses
+}
+
+// Response type for the create_group_invite endpoint
+export type CreateGroupInviteResponse = RustResponse<GroupInvite>;
+
+// Request body for the join_group_with_invite endpoint
+export interface JoinGroupWithInviteRequest {
+  JoinGroupWithInvite: string; // invite code
+}
+
+// Response type for the join_group_with_invite endpoint (group ID)
+export type JoinGroupWithInviteResponse = RustResponse<string>;
+
+// A node asking to be added to a group
+export interface JoinRequest {
+  node: string;
+  message: string;
+  requested_at: string;
+}
+
+// Request body for the request_to_join_group endpoint
+export interface RequestToJoinGroupRequest {
+  RequestToJoinGroup: [string, string, string]; // admin_node, group_id, message
+}
+
+// Response type for the request_to_join_group endpoint
+export type RequestToJoinGroupResponse = RustResponse<boolean>;
+
+// Request body for the approve_join_request endpoint
+export interface ApproveJoinRequestRequest {
+  ApproveJoinRequest: [string, string]; // group_id, node
+}
+
+// Response type for the approve_join_request endpoint
+export type ApproveJoinRequestResponse = RustResponse<boolean>;
+
+// Request body for the deny_join_request endpoint
+export interface DenyJoinRequestRequest {
+  DenyJoinRequest: [string, string]; // group_id, node
+}
+
+// Response type for the deny_join_request endpoint
+export type DenyJoinRequestResponse = RustResponse<boolean>;
+
+// Request body for the set_member_role endpoint
+export interface SetMemberRoleRequest {
+  SetMemberRole: [string, string, GroupRole]; // group_id, member, role
+}
+
+// Response type for the set_member_role endpoint
+export type SetMemberRoleResponse = RustResponse<boolean>;
+
+// Request body for the transfer_group_ownership endpoint
+export interface TransferGroupOwnershipRequest {
+  TransferGroupOwnership: [string, string]; // group_id, new_owner
+}
+
+// Response type for the transfer_group_ownership endpoint
+export type TransferGroupOwnershipResponse = RustResponse<boolean>;
+
+// Request body for the disband_group endpoint
+export interface DisbandGroupRequest {
+  DisbandGroup: string; // group_id
+}
+
+// Response type for the disband_group endpoint
+export type DisbandGroupResponse = RustResponse<boolean>;
+
+// Request body for the leave_group endpoint
+export interface LeaveGroupRequest {
+  LeaveGroup: string; // group_id
+}
+
+// Response type for the leave_group endpoint
+export type LeaveGroupResponse = RustResponse<boolean>;